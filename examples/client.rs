@@ -7,9 +7,7 @@ use std::sync::Arc;
 
 use ja_tools::builder::JAOverrideBuilder;
 use rustls::client::client_hello::CompressCertificateOptions;
-use rustls::internal::msgs::enums::ExtensionType;
-use rustls::internal::msgs::handshake::{ClientExtension, ProtocolName};
-use rustls::CipherSuite::*;
+use rustls::internal::msgs::handshake::ProtocolName;
 use rustls::{ProtocolVersion, RootCertStore, SignatureScheme};
 
 fn main() {
@@ -22,7 +20,7 @@ fn main() {
     config.alpn_protocols = vec!["http/1.1".as_bytes().to_vec()];
     let overrider = {
         // chrome 102
-        let ja3_full = "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,0-23-65281-10-11-35-16-5-13-18-51-45-43-27-17513-21,29-23-24,0";
+        let _ja3_full = "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,0-23-65281-10-11-35-16-5-13-18-51-45-43-27-17513-21,29-23-24,0";
         // chrome 120
         let ja3_full = "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,51-10-5-43-65281-35-16-11-13-23-17513-27-18-45-0-65037,29-23-24,0";
         let mut builder = JAOverrideBuilder::default();
@@ -40,14 +38,8 @@ fn main() {
             ])
             .with_tls_versions(vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2])
             .with_alpn(vec![ProtocolName::from("http/1.1".as_bytes().to_vec())])
-            .with_compress_certificate(CompressCertificateOptions::Brotli);
-        builder.unknown_extensions.insert(
-            17513,
-            ClientExtension::unknown(ExtensionType::Unknown(17513), [0x0, 0x3, 0x2, 68, 32]),
-        );
-        builder
-            .unknown_extensions
-            .insert(65037, ja_tools::extensions::grease_ech());
+            .with_alps(vec![ProtocolName::from("h2".as_bytes().to_vec())])
+            .with_compress_certificate(vec![CompressCertificateOptions::Brotli]);
         builder.with_ja3_full(ja3_full).unwrap()
     };
 
@@ -1,172 +1,558 @@
-use crate::JAOverride;
+use crate::error::{BuilderInput, Error, Ja3Field};
+use crate::extensions::{ExtensionGenerator, GreaseEch, KeyShares, PaddingMode, SniMode};
+use crate::parse::{RawClientHello, RawExtension, Reader};
+use crate::{JAOverride, TLS_GREASE_VALUES_INT};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use rustls::client::client_hello::CompressCertificateOptions;
-use rustls::internal::msgs::enums::{ECPointFormat, ExtensionType, PSKKeyExchangeMode};
-use rustls::internal::msgs::handshake::{ClientExtension, ClientSessionTicket, ProtocolName};
-use rustls::{CipherSuite, NamedGroup, ProtocolVersion, SignatureScheme};
+use rustls::crypto::CryptoProvider;
+use rustls::internal::msgs::codec::Codec;
+use rustls::internal::msgs::enums::{
+    ECPointFormat, ExtensionType, HpkeAead, HpkeKdf, HpkeKem, PSKKeyExchangeMode,
+};
+use rustls::internal::msgs::handshake::{ClientExtension, ProtocolName};
+use rustls::pki_types::EchConfigListBytes;
+use rustls::{
+    CertificateCompressionAlgorithm, CipherSuite, ClientConfig, NamedGroup, ProtocolVersion,
+    SignatureScheme,
+};
 use std::collections::HashMap;
-use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
+/// Placeholder in a cipher suite list, replaced by a random GREASE value for
+/// each ClientHello. Any other GREASE value in the list is treated the same.
+pub const GREASE_CIPHER_SUITE: CipherSuite = CipherSuite::Unknown(0x0a0a);
+
+/// Placeholder in a [`ExtensionChunk::GreasedNameGroups`] list, replaced by the
+/// GREASE group of each ClientHello. Any other GREASE value is treated the same.
+pub const GREASE_GROUP: NamedGroup = NamedGroup::Unknown(0x0a0a);
+
+/// Hybrid post-quantum group sent by current Chrome and Firefox. Shares for it
+/// come from the crypto provider; if its `kx_groups` lack it, the group is only
+/// listed in supported_groups.
+pub const X25519_MLKEM768: NamedGroup = NamedGroup::Unknown(0x11ec);
+/// Draft codepoint of the hybrid group, sent by Chrome 124 to 130.
+pub const X25519_KYBER768_DRAFT00: NamedGroup = NamedGroup::Unknown(0x6399);
+
+fn is_pq_hybrid(group: &NamedGroup) -> bool {
+    *group == X25519_MLKEM768 || *group == X25519_KYBER768_DRAFT00
+}
+
+/// Codepoints of the application_settings (ALPS) extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlpsCodepoint {
+    /// Sent by Chrome up to version 132.
+    Old = 17513,
+    /// Sent by newer Chrome.
+    New = 17613,
+}
+
+impl AlpsCodepoint {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            17513 => Some(AlpsCodepoint::Old),
+            17613 => Some(AlpsCodepoint::New),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum ExtensionChunk {
     Grease,
-    Sni,
+    /// The server_name extension, with the name [`SniMode`] picks for each
+    /// connection. Left out when there is no name to send.
+    Sni(SniMode),
     KeyShare,
+    /// Groups with [`GREASE_GROUP`] placeholders, or with a GREASE group first
+    /// if there are none.
     GreasedNameGroups(Vec<NamedGroup>),
     GreasedTLSVersion(Vec<ProtocolVersion>),
     Extension(ClientExtension),
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum FailReason<'a> {
-    Part,
-    CipherSuite(&'a str),
-    NamedGroup(&'a str),
-    ExtensionType(&'a str),
-    MissingTLSVersion,
-    MissingALPN,
-    MissingSignatureAlgorithms,
-    MissingCompressCertificate,
-}
-
-impl<'a> Display for FailReason<'a> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
+    /// Invoked once per ClientHello.
+    Generated(Arc<dyn ExtensionGenerator>),
+    /// Sized from the encoded ClientHello, see [`PaddingMode`].
+    Padding(PaddingMode),
+    /// The extension of this type rustls built, e.g. pre_shared_key, early_data
+    /// or cookie; omitted when rustls did not send one.
+    Passthrough(ExtensionType),
+    /// The TLS 1.2 ticket rustls offers for resumption, or an empty request.
+    SessionTicket,
 }
 
 #[derive(Default)]
 pub struct JAOverrideBuilder {
     pub tls_versions: Option<Vec<ProtocolVersion>>,
     pub alpn: Option<Vec<ProtocolName>>,
+    pub alps: Option<Vec<ProtocolName>>,
     pub signature_algorithms: Option<Vec<SignatureScheme>>,
-    pub compress_certificate: Option<CompressCertificateOptions>,
+    pub compress_certificate: Option<Vec<CompressCertificateOptions>>,
+    /// Decompressors of the rustls configuration, see
+    /// [`JAOverrideBuilder::with_client_config`].
+    pub cert_decompressors: Option<Vec<CertificateCompressionAlgorithm>>,
+    /// Provider of the rustls configuration, for the key shares the override
+    /// adds. Defaults to the process-wide default provider.
+    pub crypto_provider: Option<Arc<CryptoProvider>>,
     // empirical
     pub grease: bool,
     pub unknown_extensions: HashMap<u16, ClientExtension>,
     pub shuffle_extension: bool,
+    pub padding: PaddingMode,
+    pub sni: SniMode,
+    pub pinned_extensions: Vec<ExtensionType>,
+    pub seed: Option<u64>,
+    pub ech_config_list: Option<EchConfigListBytes<'static>>,
+    // explicit profile for `build`
+    pub cipher_suites: Vec<CipherSuite>,
+    pub extensions: Vec<ExtensionChunk>,
+}
+
+impl ExtensionChunk {
+    /// Forwards the server name rustls sends.
+    pub fn sni() -> Self {
+        ExtensionChunk::Sni(SniMode::Forward)
+    }
+
+    pub fn alpn(protocols: &[&str]) -> Self {
+        ExtensionChunk::Extension(ClientExtension::Protocols(
+            protocols
+                .iter()
+                .map(|p| ProtocolName::from(p.as_bytes().to_vec()))
+                .collect(),
+        ))
+    }
+
+    /// ALPS with the protocols that have application settings, e.g. `h2`.
+    pub fn application_settings(protocols: &[ProtocolName], codepoint: AlpsCodepoint) -> Self {
+        let mut list = Vec::new();
+        for protocol in protocols {
+            list.push(protocol.as_ref().len() as u8);
+            list.extend_from_slice(protocol.as_ref());
+        }
+        let mut payload = Vec::with_capacity(list.len() + 2);
+        payload.extend_from_slice(&(list.len() as u16).to_be_bytes());
+        payload.extend_from_slice(&list);
+        ExtensionChunk::Extension(ClientExtension::unknown(
+            ExtensionType::Unknown(codepoint as u16),
+            payload,
+        ))
+    }
+
+    pub fn named_groups(groups: Vec<NamedGroup>, grease: bool) -> Self {
+        if grease {
+            ExtensionChunk::GreasedNameGroups(groups)
+        } else {
+            ExtensionChunk::Extension(ClientExtension::NamedGroups(groups))
+        }
+    }
+
+    pub fn tls_versions(versions: Vec<ProtocolVersion>, grease: bool) -> Self {
+        if grease {
+            ExtensionChunk::GreasedTLSVersion(versions)
+        } else {
+            ExtensionChunk::Extension(ClientExtension::SupportedVersions(versions))
+        }
+    }
+
+    pub fn signature_algorithms(schemes: Vec<SignatureScheme>) -> Self {
+        ExtensionChunk::Extension(ClientExtension::SignatureAlgorithms(schemes))
+    }
+
+    /// Key share with an optional GREASE entry and one share per group, see
+    /// [`KeyShares`].
+    pub fn key_share(grease: bool, groups: Vec<NamedGroup>) -> Self {
+        ExtensionChunk::generator(KeyShares {
+            grease,
+            groups,
+            provider: None,
+        })
+    }
+
+    /// The payload the JA3 importer uses for extensions that need no input,
+    /// e.g. extended_master_secret or renegotiation_info.
+    pub fn with_default_payload(ext_ty: ExtensionType) -> Option<Self> {
+        convert_extension(ext_ty)
+    }
 }
 
 impl JAOverrideBuilder {
-    pub fn with_ja3_full<'a>(&self, ja3: &'a str) -> Result<JAOverride, FailReason<'a>> {
+    /// Empty fields are accepted as empty lists. GREASE values in the extension
+    /// and group lists are regenerated per connection at the same positions.
+    pub fn with_ja3_full(&self, ja3: &str) -> Result<JAOverride, Error> {
         let parts: Vec<&str> = ja3.split(',').collect();
         if parts.len() != 5 {
-            return Err(FailReason::Part);
+            return Err(Error::Ja3FieldCount(parts.len()));
         }
-        let cipher_suites = {
-            let mut suites = Vec::new();
-            for suite in parts[1].split('-') {
-                suites.push(CipherSuite::from(
-                    suite
-                        .parse::<u16>()
-                        .map_err(|_| FailReason::CipherSuite(suite))?,
-                ));
-            }
-            suites
-        };
-        let named_groups = {
-            let mut groups = Vec::new();
-            for group in parts[3].split('-') {
-                groups.push(NamedGroup::from(
-                    group
-                        .parse::<u16>()
-                        .map_err(|_| FailReason::NamedGroup(group))?,
-                ));
-            }
-            groups
-        };
+        let legacy_version =
+            ProtocolVersion::from(parts[0].parse::<u16>().map_err(|_| Error::InvalidNumber {
+                field: Ja3Field::Version,
+                index: 0,
+                value: parts[0].to_string(),
+            })?);
+        let mut cipher_suites: Vec<CipherSuite> =
+            parse_ja3_field::<u16>(Ja3Field::CipherSuites, parts[1])?
+                .into_iter()
+                .map(CipherSuite::from)
+                .collect();
+        // JA3 strings usually have GREASE stripped, browsers send it first
+        if self.grease
+            && !cipher_suites
+                .iter()
+                .any(|c| TLS_GREASE_VALUES_INT.contains(&u16::from(*c)))
+        {
+            cipher_suites.insert(0, GREASE_CIPHER_SUITE);
+        }
+        // GREASE groups are regenerated by GreasedNameGroups at the same positions
+        let named_groups = parse_ja3_field::<u16>(Ja3Field::NamedGroups, parts[3])?
+            .into_iter()
+            .map(NamedGroup::from)
+            .collect::<Vec<_>>();
+        let greased_groups = self.grease
+            || named_groups
+                .iter()
+                .any(|g| TLS_GREASE_VALUES_INT.contains(&u16::from(*g)));
+        let point_formats = parse_ja3_field::<u8>(Ja3Field::ECPointFormats, parts[4])?
+            .into_iter()
+            .map(ECPointFormat::from)
+            .collect::<Vec<_>>();
+        let extension_ids = parse_ja3_field::<u16>(Ja3Field::Extensions, parts[2])?;
+        // GREASE extensions listed in the string take the place of the default ones
+        let explicit_grease = extension_ids
+            .iter()
+            .any(|id| TLS_GREASE_VALUES_INT.contains(id));
         let extensions = {
-            let mut exts = if self.grease {
+            let mut exts = if self.grease && !explicit_grease {
                 vec![ExtensionChunk::Grease]
             } else {
                 Vec::new()
             };
-            for ext in parts[2].split('-') {
-                exts.push(
-                    match ExtensionType::from(
-                        ext.parse::<u16>()
-                            .map_err(|_| FailReason::ExtensionType(ext))?,
-                    ) {
-                        ExtensionType::EllipticCurves => {
-                            if self.grease {
-                                ExtensionChunk::GreasedNameGroups(named_groups.clone())
-                            } else {
-                                ExtensionChunk::Extension(ClientExtension::NamedGroups(
-                                    named_groups.clone(),
-                                ))
-                            }
-                        }
-                        ExtensionType::SupportedVersions => {
-                            if self.grease {
-                                ExtensionChunk::GreasedTLSVersion(
-                                    self.tls_versions
-                                        .clone()
-                                        .ok_or(FailReason::MissingTLSVersion)?,
-                                )
-                            } else {
-                                ExtensionChunk::Extension(ClientExtension::SupportedVersions(
-                                    self.tls_versions
-                                        .clone()
-                                        .ok_or(FailReason::MissingTLSVersion)?,
-                                ))
-                            }
-                        }
-                        ExtensionType::ALProtocolNegotiation => {
-                            ExtensionChunk::Extension(ClientExtension::Protocols(
-                                self.alpn.clone().ok_or(FailReason::MissingALPN)?,
+            for (index, id) in extension_ids.into_iter().enumerate() {
+                if TLS_GREASE_VALUES_INT.contains(&id) {
+                    exts.push(ExtensionChunk::Grease);
+                    continue;
+                }
+                exts.push(match ExtensionType::from(id) {
+                    ExtensionType::EllipticCurves => {
+                        if greased_groups {
+                            ExtensionChunk::GreasedNameGroups(named_groups.clone())
+                        } else {
+                            ExtensionChunk::Extension(ClientExtension::NamedGroups(
+                                named_groups.clone(),
                             ))
                         }
-                        ExtensionType::SignatureAlgorithms => {
-                            ExtensionChunk::Extension(ClientExtension::SignatureAlgorithms(
-                                self.signature_algorithms
+                    }
+                    ExtensionType::ECPointFormats => ExtensionChunk::Extension(
+                        ClientExtension::EcPointFormats(point_formats.clone()),
+                    ),
+                    ExtensionType::SupportedVersions => {
+                        if self.grease {
+                            ExtensionChunk::GreasedTLSVersion(
+                                self.tls_versions
+                                    .clone()
+                                    .ok_or(Error::MissingInput(BuilderInput::TLSVersions))?,
+                            )
+                        } else {
+                            ExtensionChunk::Extension(ClientExtension::SupportedVersions(
+                                self.tls_versions
                                     .clone()
-                                    .ok_or(FailReason::MissingSignatureAlgorithms)?,
+                                    .ok_or(Error::MissingInput(BuilderInput::TLSVersions))?,
                             ))
                         }
-                        ExtensionType::CompressCertificate => {
-                            ExtensionChunk::Extension(ClientExtension::compress_certificate(&[
-                                self.compress_certificate
-                                    .ok_or(FailReason::MissingCompressCertificate)?,
-                            ]))
+                    }
+                    ExtensionType::ALProtocolNegotiation => {
+                        ExtensionChunk::Extension(ClientExtension::Protocols(
+                            self.alpn
+                                .clone()
+                                .ok_or(Error::MissingInput(BuilderInput::ALPN))?,
+                        ))
+                    }
+                    // browsers pair the hybrid share with one for the most
+                    // preferred classical group (RFC 8446 4.2.8); a hybrid
+                    // group the provider lacks stays in supported_groups
+                    ExtensionType::KeyShare => {
+                        let provider = self
+                            .crypto_provider
+                            .clone()
+                            .or_else(|| CryptoProvider::get_default().cloned());
+                        let supported = |group: &NamedGroup| {
+                            provider
+                                .iter()
+                                .any(|p| p.kx_groups.iter().any(|kx| kx.name() == *group))
+                        };
+                        let pq = named_groups
+                            .iter()
+                            .find(|g| is_pq_hybrid(g) && supported(g));
+                        let classical = named_groups
+                            .iter()
+                            .find(|g| !is_pq_hybrid(g) && supported(g));
+                        match pq {
+                            Some(pq) => ExtensionChunk::generator(KeyShares {
+                                grease: self.grease,
+                                groups: std::iter::once(*pq).chain(classical.copied()).collect(),
+                                provider,
+                            }),
+                            None if self.grease => ExtensionChunk::generator(KeyShares {
+                                grease: true,
+                                groups: Vec::new(),
+                                provider,
+                            }),
+                            None => ExtensionChunk::KeyShare,
                         }
-                        oth => {
-                            if let Some(exten) = convert_extension(oth) {
-                                exten
-                            } else {
-                                ExtensionChunk::Extension(
-                                    self.unknown_extensions
-                                        .get(&u16::from(oth))
-                                        .ok_or(FailReason::ExtensionType(ext))?
-                                        .clone(),
-                                )
-                            }
+                    }
+                    ExtensionType::SignatureAlgorithms => {
+                        ExtensionChunk::Extension(ClientExtension::SignatureAlgorithms(
+                            self.signature_algorithms
+                                .clone()
+                                .ok_or(Error::MissingInput(BuilderInput::SignatureAlgorithms))?,
+                        ))
+                    }
+                    ExtensionType::CompressCertificate => {
+                        ExtensionChunk::Extension(ClientExtension::compress_certificate(
+                            self.compress_certificate
+                                .as_ref()
+                                .ok_or(Error::MissingInput(BuilderInput::CompressCertificate))?,
+                        ))
+                    }
+                    ExtensionType::Padding => ExtensionChunk::Padding(self.padding),
+                    ExtensionType::ServerName => ExtensionChunk::Sni(self.sni.clone()),
+                    oth => {
+                        if let Some(exten) = convert_extension(oth) {
+                            exten
+                        } else if let (Some(codepoint), false) = (
+                            AlpsCodepoint::from_u16(id),
+                            self.unknown_extensions.contains_key(&id),
+                        ) {
+                            ExtensionChunk::application_settings(
+                                self.alps
+                                    .as_ref()
+                                    .ok_or(Error::MissingInput(BuilderInput::ALPS))?,
+                                codepoint,
+                            )
+                        } else {
+                            ExtensionChunk::Extension(
+                                self.unknown_extensions
+                                    .get(&u16::from(oth))
+                                    .ok_or(Error::UnregisteredExtension {
+                                        index,
+                                        extension: id,
+                                    })?
+                                    .clone(),
+                            )
                         }
-                    },
-                );
-            }
-            if self.grease {
-                if let Some(ExtensionChunk::Extension(e)) = exts.last() {
-                    if e.get_ext_type() == ExtensionType::Padding {
-                        exts.insert(exts.len() - 1, ExtensionChunk::Grease);
-                    } else {
-                        exts.push(ExtensionChunk::Grease);
                     }
+                });
+            }
+            if self.grease && !explicit_grease {
+                if let Some(ExtensionChunk::Padding(_)) = exts.last() {
+                    exts.insert(exts.len() - 1, ExtensionChunk::Grease);
                 } else {
                     exts.push(ExtensionChunk::Grease);
                 }
             }
             exts
         };
-        Ok(JAOverride {
+        self.finish(legacy_version, cipher_suites, extensions)
+    }
+}
+
+impl JAOverrideBuilder {
+    /// Import a captured ClientHello, either as a bare handshake message or as
+    /// TLS records. Extension payloads are kept byte-for-byte, except for the
+    /// ones that must be generated per connection (SNI, key share, GREASE).
+    pub fn from_client_hello_bytes(&self, bytes: &[u8]) -> Result<JAOverride, Error> {
+        let hello = RawClientHello::parse(bytes)?;
+        let cipher_suites = hello
+            .cipher_suites
+            .iter()
+            .map(|c| CipherSuite::from(*c))
+            .collect();
+        let extensions = hello
+            .extensions
+            .iter()
+            .map(
+                |ext| match convert_raw_extension(ext, self.crypto_provider.as_ref()) {
+                    ExtensionChunk::Sni(_) => ExtensionChunk::Sni(self.sni.clone()),
+                    chunk => chunk,
+                },
+            )
+            .collect();
+        self.finish(
+            ProtocolVersion::from(hello.legacy_version),
             cipher_suites,
             extensions,
-            shuffle_extension: self.shuffle_extension,
-        })
+        )
     }
 }
 
+fn convert_raw_extension(
+    ext: &RawExtension,
+    provider: Option<&Arc<CryptoProvider>>,
+) -> ExtensionChunk {
+    if TLS_GREASE_VALUES_INT.contains(&ext.typ) {
+        return ExtensionChunk::Grease;
+    }
+    match decode_raw_extension(ext, provider) {
+        Ok(Some(chunk)) => chunk,
+        _ => ExtensionChunk::Extension(ClientExtension::unknown(
+            ExtensionType::from(ext.typ),
+            ext.payload.clone(),
+        )),
+    }
+}
+
+/// Decode the extensions that the override or the fingerprinting code needs to
+/// understand. Anything else, or anything that would not re-encode verbatim, is
+/// left to the caller to keep as a raw payload. Generated key shares come from
+/// `provider`.
+fn decode_raw_extension(
+    ext: &RawExtension,
+    provider: Option<&Arc<CryptoProvider>>,
+) -> Result<Option<ExtensionChunk>, Error> {
+    let mut r = Reader::new(&ext.payload);
+    let chunk = match ExtensionType::from(ext.typ) {
+        // filled in per connection, the captured payload is irrelevant
+        ExtensionType::ServerName => return Ok(Some(ExtensionChunk::Sni(SniMode::Forward))),
+        ExtensionType::PreSharedKey | ExtensionType::EarlyData | ExtensionType::Cookie => {
+            return Ok(Some(ExtensionChunk::Passthrough(ExtensionType::from(
+                ext.typ,
+            ))))
+        }
+        // a captured ticket is bound to another session
+        ExtensionType::SessionTicket => return Ok(Some(ExtensionChunk::SessionTicket)),
+        ExtensionType::KeyShare => {
+            let mut grease = false;
+            let mut groups = Vec::new();
+            let mut entries = Reader::new(r.vec_u16()?);
+            while !entries.is_empty() {
+                let group = entries.u16()?;
+                entries.vec_u16()?;
+                if TLS_GREASE_VALUES_INT.contains(&group) {
+                    grease = true;
+                } else {
+                    groups.push(NamedGroup::from(group));
+                }
+            }
+            if !grease && groups.len() <= 1 {
+                return Ok(Some(ExtensionChunk::KeyShare));
+            }
+            ExtensionChunk::generator(KeyShares {
+                grease,
+                groups,
+                provider: provider.cloned(),
+            })
+        }
+        // a replayed ECH payload would be identical on every connection, so
+        // only the HPKE suite is kept
+        ExtensionType::EncryptedClientHello => {
+            let mut ech = GreaseEch::default();
+            if r.u8()? == 0 {
+                ech.kdf = HpkeKdf::from(r.u16()?);
+                ech.aead = HpkeAead::from(r.u16()?);
+                r.u8()?;
+                ech.kem = match r.vec_u16()?.len() {
+                    65 => HpkeKem::DHKEM_P256_HKDF_SHA256,
+                    97 => HpkeKem::DHKEM_P384_HKDF_SHA384,
+                    133 => HpkeKem::DHKEM_P521_HKDF_SHA512,
+                    56 => HpkeKem::DHKEM_X448_HKDF_SHA512,
+                    _ => HpkeKem::DHKEM_X25519_HKDF_SHA256,
+                };
+            }
+            return Ok(Some(ExtensionChunk::generator(ech)));
+        }
+        ExtensionType::EllipticCurves => {
+            let groups = Reader::new(r.vec_u16()?).u16_list()?;
+            let greased = groups.iter().any(|g| TLS_GREASE_VALUES_INT.contains(g));
+            let groups = groups.into_iter().map(NamedGroup::from).collect();
+            if greased {
+                ExtensionChunk::GreasedNameGroups(groups)
+            } else {
+                ExtensionChunk::Extension(ClientExtension::NamedGroups(groups))
+            }
+        }
+        ExtensionType::SupportedVersions => {
+            let (greased, versions) = strip_leading_grease(Reader::new(r.vec_u8()?).u16_list()?)?;
+            let versions = versions.into_iter().map(ProtocolVersion::from).collect();
+            if greased {
+                ExtensionChunk::GreasedTLSVersion(versions)
+            } else {
+                ExtensionChunk::Extension(ClientExtension::SupportedVersions(versions))
+            }
+        }
+        ExtensionType::SignatureAlgorithms => {
+            ExtensionChunk::Extension(ClientExtension::SignatureAlgorithms(
+                Reader::new(r.vec_u16()?)
+                    .u16_list()?
+                    .into_iter()
+                    .map(SignatureScheme::from)
+                    .collect(),
+            ))
+        }
+        ExtensionType::ALProtocolNegotiation => {
+            let mut protocols = Vec::new();
+            let mut sub = Reader::new(r.vec_u16()?);
+            while !sub.is_empty() {
+                protocols.push(ProtocolName::from(sub.vec_u8()?.to_vec()));
+            }
+            ExtensionChunk::Extension(ClientExtension::Protocols(protocols))
+        }
+        ExtensionType::ECPointFormats => {
+            ExtensionChunk::Extension(ClientExtension::EcPointFormats(
+                r.vec_u8()?
+                    .iter()
+                    .map(|f| ECPointFormat::from(*f))
+                    .collect(),
+            ))
+        }
+        // the captured length only fits the captured SNI
+        ExtensionType::Padding => return Ok(Some(ExtensionChunk::Padding(PaddingMode::default()))),
+        _ => return Ok(None),
+    };
+    if !r.is_empty() {
+        return Err(Error::MalformedHello("trailing bytes in extension"));
+    }
+    Ok(Some(chunk))
+}
+
+/// Only a GREASE value in the first position can be regenerated by
+/// GreasedTLSVersion; anything else has to be kept verbatim.
+fn strip_leading_grease(mut values: Vec<u16>) -> Result<(bool, Vec<u16>), Error> {
+    let greased = values
+        .first()
+        .is_some_and(|v| TLS_GREASE_VALUES_INT.contains(v));
+    if greased {
+        values.remove(0);
+    }
+    if values.iter().any(|v| TLS_GREASE_VALUES_INT.contains(v)) {
+        return Err(Error::MalformedHello(
+            "GREASE value in an unsupported position",
+        ));
+    }
+    Ok((greased, values))
+}
+
+/// JA3 lists are dash-separated, and an empty field is an empty list.
+fn parse_ja3_field<T: FromStr>(field: Ja3Field, part: &str) -> Result<Vec<T>, Error> {
+    part.split('-')
+        .filter(|_| !part.is_empty())
+        .enumerate()
+        .map(|(index, value)| {
+            value.parse::<T>().map_err(|_| Error::InvalidNumber {
+                field,
+                index,
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
 fn convert_extension(ext_ty: ExtensionType) -> Option<ExtensionChunk> {
     match ext_ty {
-        ExtensionType::ServerName => Some(ExtensionChunk::Sni),
+        ExtensionType::ServerName => Some(ExtensionChunk::Sni(SniMode::Forward)),
         ExtensionType::KeyShare => Some(ExtensionChunk::KeyShare),
+        ExtensionType::PreSharedKey | ExtensionType::EarlyData | ExtensionType::Cookie => {
+            Some(ExtensionChunk::Passthrough(ext_ty))
+        }
         ExtensionType::ExtendedMasterSecret => Some(ExtensionChunk::Extension(
             ClientExtension::ExtendedMasterSecretRequest,
         )),
@@ -179,16 +565,14 @@ fn convert_extension(ext_ty: ExtensionType) -> Option<ExtensionChunk> {
         ExtensionType::SCT => Some(ExtensionChunk::Extension(
             ClientExtension::signed_certificate_timestamp(),
         )),
-        ExtensionType::Padding => Some(ExtensionChunk::Extension(ClientExtension::padding(vec![]))),
+        ExtensionType::Padding => Some(ExtensionChunk::Padding(PaddingMode::default())),
         ExtensionType::PSKKeyExchangeModes => Some(ExtensionChunk::Extension(
             ClientExtension::PresharedKeyModes(vec![PSKKeyExchangeMode::PSK_DHE_KE]),
         )),
-        ExtensionType::SessionTicket => Some(ExtensionChunk::Extension(
-            ClientExtension::SessionTicket(ClientSessionTicket::Request),
-        )),
-        ExtensionType::ECPointFormats => Some(ExtensionChunk::Extension(
-            ClientExtension::EcPointFormats(vec![ECPointFormat::Uncompressed]),
-        )),
+        ExtensionType::SessionTicket => Some(ExtensionChunk::SessionTicket),
+        ExtensionType::EncryptedClientHello => {
+            Some(ExtensionChunk::generator(GreaseEch::default()))
+        }
         _ => None,
     }
 }
@@ -204,6 +588,24 @@ impl JAOverrideBuilder {
         self
     }
 
+    pub fn with_padding(&mut self, padding: PaddingMode) -> &mut Self {
+        self.padding = padding;
+        self
+    }
+
+    /// What imported profiles send as server_name, when they send one.
+    pub fn with_sni(&mut self, sni: SniMode) -> &mut Self {
+        self.sni = sni;
+        self
+    }
+
+    /// Keep an extension at its position when shuffling. GREASE, padding and
+    /// pre_shared_key are always pinned.
+    pub fn with_pinned_extension(&mut self, typ: ExtensionType) -> &mut Self {
+        self.pinned_extensions.push(typ);
+        self
+    }
+
     pub fn with_tls_versions(&mut self, tls_versions: Vec<ProtocolVersion>) -> &mut Self {
         self.tls_versions = Some(tls_versions);
         self
@@ -214,6 +616,13 @@ impl JAOverrideBuilder {
         self
     }
 
+    /// Protocols listed in the application_settings extension, under either
+    /// codepoint.
+    pub fn with_alps(&mut self, alps: Vec<ProtocolName>) -> &mut Self {
+        self.alps = Some(alps);
+        self
+    }
+
     pub fn with_signature_algorithms(
         &mut self,
         signature_algorithms: Vec<SignatureScheme>,
@@ -222,17 +631,131 @@ impl JAOverrideBuilder {
         self
     }
 
+    /// Algorithms advertised in compress_certificate, in order. See
+    /// [`Self::with_client_config`].
     pub fn with_compress_certificate(
         &mut self,
-        compress_certificate: CompressCertificateOptions,
+        compress_certificate: Vec<CompressCertificateOptions>,
     ) -> &mut Self {
         self.compress_certificate = Some(compress_certificate);
         self
     }
+
+    /// Check built and imported profiles against the configuration they are
+    /// used with. A server may compress its certificate with any algorithm in
+    /// compress_certificate, so each of them needs a decompressor in `config`.
+    ///
+    /// Key shares the override adds are generated by the config's provider.
+    pub fn with_client_config(&mut self, config: &ClientConfig) -> &mut Self {
+        self.crypto_provider = Some(config.crypto_provider().clone());
+        self.cert_decompressors = Some(
+            config
+                .cert_decompressors
+                .iter()
+                .map(|d| d.algorithm())
+                .collect(),
+        );
+        self
+    }
+
+    pub fn with_cipher_suites(&mut self, cipher_suites: Vec<CipherSuite>) -> &mut Self {
+        self.cipher_suites = cipher_suites;
+        self
+    }
+
+    /// Append an extension to the profile used by [`JAOverrideBuilder::build`].
+    pub fn push_extension(&mut self, extension: ExtensionChunk) -> &mut Self {
+        self.extensions.push(extension);
+        self
+    }
+
+    pub fn with_extensions(&mut self, extensions: Vec<ExtensionChunk>) -> &mut Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Build an override from the explicit cipher suites and extensions, sent
+    /// exactly in the given order.
+    pub fn build(&self) -> Result<JAOverride, Error> {
+        self.finish(
+            ProtocolVersion::TLSv1_2,
+            self.cipher_suites.clone(),
+            self.extensions.clone(),
+        )
+    }
+
+    /// Make GREASE values, shuffling and generated extensions reproducible.
+    /// Each connection draws its own seed from this one, which is logged at
+    /// debug level.
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Offer real ECH with this ECHConfigList, e.g. from a DNS HTTPS record or
+    /// [`crate::ech::retry_configs`]. See [`JAOverride::ech_mode`].
+    pub fn with_ech_config_list(&mut self, list: impl Into<Vec<u8>>) -> &mut Self {
+        self.ech_config_list = Some(EchConfigListBytes::from(list.into()));
+        self
+    }
+
+    fn finish(
+        &self,
+        legacy_version: ProtocolVersion,
+        cipher_suites: Vec<CipherSuite>,
+        extensions: Vec<ExtensionChunk>,
+    ) -> Result<JAOverride, Error> {
+        if let Some(available) = &self.cert_decompressors {
+            for chunk in extensions.iter() {
+                if let ExtensionChunk::Extension(ext) = chunk {
+                    check_decompressors(ext, available)?;
+                }
+            }
+        }
+        let rng = match self.seed {
+            Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+            None => ChaCha20Rng::from_entropy(),
+        };
+        Ok(JAOverride {
+            legacy_version,
+            cipher_suites: cipher_suites.into(),
+            extensions: extensions.into(),
+            shuffle_extension: self.shuffle_extension,
+            pinned_extensions: self.pinned_extensions.as_slice().into(),
+            ech_config_list: self.ech_config_list.clone(),
+            rng: Mutex::new(rng),
+        })
+    }
 }
 
+/// Every algorithm a compress_certificate extension advertises, typed or kept
+/// as a captured payload, needs a decompressor.
+fn check_decompressors(
+    ext: &ClientExtension,
+    available: &[CertificateCompressionAlgorithm],
+) -> Result<(), Error> {
+    if ext.get_ext_type() != ExtensionType::CompressCertificate {
+        return Ok(());
+    }
+    let encoding = ext.get_encoding();
+    let mut r = Reader::new(&encoding);
+    r.take(4)?;
+    let algorithms = Reader::new(r.vec_u8()?).u16_list()?;
+    for algorithm in algorithms
+        .into_iter()
+        .map(CertificateCompressionAlgorithm::from)
+    {
+        if !available.contains(&algorithm) {
+            return Err(Error::NoDecompressor(algorithm));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_util::*;
 
     #[test]
     fn test_ja3_full() {
@@ -252,18 +775,313 @@ mod test {
             ])
             .with_tls_versions(vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2])
             .with_alpn(vec![ProtocolName::from("http/1.1".as_bytes().to_vec())])
-            .with_compress_certificate(CompressCertificateOptions::Brotli);
+            .with_compress_certificate(vec![CompressCertificateOptions::Brotli]);
         builder.unknown_extensions.insert(
             17513,
             ClientExtension::unknown(ExtensionType::Unknown(17513), [0x0, 0x3, 0x2, 68, 32]),
         );
         let overrider = builder.with_ja3_full(ja3_full).unwrap();
+        // GREASE first and last
+        assert_eq!(overrider.extensions.len(), 18);
         #[cfg(feature = "ja3")]
         {
             assert_eq!(overrider.ja3_full(), ja3_full);
             assert_eq!(overrider.ja3_hash(), "cd08e31494f9531f560d64c695473da9");
         }
         #[cfg(feature = "ja4")]
-        assert_eq!(overrider.ja4_hash(), "t13d1514h1_8daaf6152771_e5627efa2ab1");
+        assert_eq!(overrider.ja4_hash(), "t13d1516h1_8daaf6152771_e5627efa2ab1");
+    }
+
+    #[test]
+    fn test_from_client_hello_bytes() {
+        let record = decode_hex(CHROME_HELLO_RECORD);
+        let overrider = JAOverrideBuilder::default()
+            .from_client_hello_bytes(&record)
+            .unwrap();
+        // the record header is optional
+        let bare = JAOverrideBuilder::default()
+            .from_client_hello_bytes(&record[5..])
+            .unwrap();
+        assert_eq!(overrider.cipher_suites, bare.cipher_suites);
+        assert_eq!(overrider.cipher_suites.len(), 16);
+        assert_eq!(overrider.extensions.len(), 18);
+        assert!(matches!(overrider.extensions[0], ExtensionChunk::Grease));
+        assert!(matches!(overrider.extensions[1], ExtensionChunk::Sni(_)));
+        assert!(matches!(
+            overrider.extensions[4],
+            ExtensionChunk::GreasedNameGroups(_)
+        ));
+        // the captured key share has a GREASE entry
+        assert!(matches!(
+            &overrider.extensions[11],
+            ExtensionChunk::Generated(g) if g.ext_type() == ExtensionType::KeyShare
+        ));
+        assert!(matches!(
+            overrider.extensions[13],
+            ExtensionChunk::GreasedTLSVersion(_)
+        ));
+        assert!(matches!(overrider.extensions[16], ExtensionChunk::Grease));
+        assert!(matches!(
+            overrider.extensions[17],
+            ExtensionChunk::Padding(PaddingMode::BoringSsl)
+        ));
+        #[cfg(feature = "ja4")]
+        assert_eq!(overrider.ja4_hash(), "t13d1516h1_8daaf6152771_e5627efa2ab1");
+        assert!(JAOverrideBuilder::default()
+            .from_client_hello_bytes(&record[..100])
+            .is_err());
+    }
+
+    #[test]
+    fn test_ja3_version_and_point_formats() {
+        let ja3_full = "769,47-53-10,0-11-10-35,23-24,0-1-2";
+        let overrider = JAOverrideBuilder::default()
+            .with_ja3_full(ja3_full)
+            .unwrap();
+        assert_eq!(overrider.legacy_version, ProtocolVersion::TLSv1_0);
+        assert!(overrider.extensions.iter().any(|e| matches!(
+            e,
+            ExtensionChunk::Extension(ClientExtension::EcPointFormats(f)) if f.len() == 3
+        )));
+        #[cfg(feature = "ja3")]
+        assert_eq!(overrider.ja3_full(), ja3_full);
+    }
+
+    #[test]
+    fn test_ja3_empty_fields_and_grease() {
+        let overrider = JAOverrideBuilder::default()
+            .with_ja3_full("771,47-53,0-23,,")
+            .unwrap();
+        assert_eq!(overrider.extensions.len(), 2);
+        #[cfg(feature = "ja3")]
+        assert_eq!(overrider.ja3_full(), "771,47-53,0-23,,");
+
+        let overrider = JAOverrideBuilder::default()
+            .with_grease(true)
+            .with_ja3_full("771,2570-4865,2570-0-10-11-6682,2570-29-23,0")
+            .unwrap();
+        assert!(matches!(overrider.extensions[0], ExtensionChunk::Grease));
+        assert!(matches!(
+            &overrider.extensions[2],
+            ExtensionChunk::GreasedNameGroups(g) if g.len() == 3
+        ));
+        assert!(matches!(overrider.extensions[4], ExtensionChunk::Grease));
+        assert_eq!(overrider.extensions.len(), 5);
+        assert_eq!(overrider.cipher_suites.len(), 2);
+        #[cfg(feature = "ja3")]
+        assert_eq!(overrider.ja3_full(), "771,4865,0-10-11,29-23,0");
+
+        // GREASE stays where the string has it
+        use rustls::client::client_hello::ClientHelloOverride;
+        let overrider = JAOverrideBuilder::default()
+            .with_ja3_full("771,4865,10,29-6682-23,")
+            .unwrap();
+        let sent = overrider.override_extensions(vec![]);
+        let ClientExtension::NamedGroups(groups) = &sent[0] else {
+            panic!("expected supported_groups");
+        };
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0], NamedGroup::X25519);
+        assert!(TLS_GREASE_VALUES_INT.contains(&u16::from(groups[1])));
+        assert_eq!(groups[2], NamedGroup::secp256r1);
+    }
+
+    #[test]
+    fn test_build() {
+        let mut builder = JAOverride::builder();
+        builder
+            .with_cipher_suites(vec![
+                CipherSuite::TLS13_AES_128_GCM_SHA256,
+                CipherSuite::TLS13_CHACHA20_POLY1305_SHA256,
+            ])
+            .push_extension(ExtensionChunk::Grease)
+            .push_extension(ExtensionChunk::sni())
+            .push_extension(ExtensionChunk::named_groups(
+                vec![NamedGroup::X25519, NamedGroup::secp256r1],
+                true,
+            ))
+            .push_extension(ExtensionChunk::alpn(&["h2", "http/1.1"]))
+            .push_extension(ExtensionChunk::signature_algorithms(vec![
+                SignatureScheme::ECDSA_NISTP256_SHA256,
+            ]))
+            .push_extension(ExtensionChunk::KeyShare)
+            .push_extension(ExtensionChunk::tls_versions(
+                vec![ProtocolVersion::TLSv1_3],
+                true,
+            ))
+            .push_extension(
+                ExtensionChunk::with_default_payload(ExtensionType::ExtendedMasterSecret).unwrap(),
+            )
+            .push_extension(ExtensionChunk::Grease);
+        let overrider = builder.build().unwrap();
+        assert_eq!(overrider.extensions.len(), 9);
+        #[cfg(feature = "ja3")]
+        assert_eq!(
+            overrider.ja3_full(),
+            "771,4865-4867,0-10-16-13-51-43-23,29-23,"
+        );
+        #[cfg(feature = "ja4")]
+        assert!(overrider.ja4_hash().starts_with("t13d0207h2_"));
+    }
+
+    #[test]
+    fn test_alps() {
+        use rustls::internal::msgs::codec::Codec;
+        let ja3 = "771,4865,17513-17613,,";
+        assert_eq!(
+            JAOverrideBuilder::default().with_ja3_full(ja3).unwrap_err(),
+            Error::MissingInput(BuilderInput::ALPS)
+        );
+        let overrider = JAOverrideBuilder::default()
+            .with_alps(vec![ProtocolName::from(b"h2".to_vec())])
+            .with_ja3_full(ja3)
+            .unwrap();
+        let ids: Vec<u16> = overrider
+            .extensions
+            .iter()
+            .map(|chunk| match chunk {
+                ExtensionChunk::Extension(ext) => {
+                    let encoding = ext.get_encoding();
+                    assert_eq!(encoding[2..], [0, 5, 0, 3, 2, b'h', b'2']);
+                    u16::from(ext.get_ext_type())
+                }
+                _ => panic!("ALPS is not a fixed extension"),
+            })
+            .collect();
+        assert_eq!(ids, [17513, 17613]);
+        #[cfg(feature = "ja3")]
+        assert_eq!(overrider.ja3_full(), ja3);
+    }
+
+    #[test]
+    fn test_compress_certificate() {
+        use rustls::compress::{CertDecompressor, DecompressionFailed};
+        use rustls::internal::msgs::codec::Codec;
+        use CompressCertificateOptions::*;
+
+        #[derive(Debug)]
+        struct Fake(CertificateCompressionAlgorithm);
+        impl CertDecompressor for Fake {
+            fn decompress(&self, _: &[u8], _: &mut [u8]) -> Result<(), DecompressionFailed> {
+                Err(DecompressionFailed)
+            }
+            fn algorithm(&self) -> CertificateCompressionAlgorithm {
+                self.0
+            }
+        }
+        static ZLIB: Fake = Fake(CertificateCompressionAlgorithm::Zlib);
+        static BROTLI: Fake = Fake(CertificateCompressionAlgorithm::Brotli);
+
+        let mut config = ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        config.cert_decompressors = vec![&ZLIB, &BROTLI];
+        let mut builder = JAOverrideBuilder::default();
+        builder.with_client_config(&config);
+        // Firefox
+        builder.with_compress_certificate(vec![Zlib, Brotli, Zstd]);
+        assert_eq!(
+            builder.with_ja3_full("771,4865,27,,").unwrap_err(),
+            Error::NoDecompressor(CertificateCompressionAlgorithm::Zstd)
+        );
+        // a captured hello with brotli only
+        config.cert_decompressors = vec![&ZLIB];
+        assert_eq!(
+            JAOverrideBuilder::default()
+                .with_client_config(&config)
+                .from_client_hello_bytes(&decode_hex(CHROME_HELLO_RECORD))
+                .unwrap_err(),
+            Error::NoDecompressor(CertificateCompressionAlgorithm::Brotli)
+        );
+        config.cert_decompressors = vec![&ZLIB, &BROTLI];
+        builder.with_compress_certificate(vec![Brotli, Zlib]);
+        assert!(builder
+            .with_extensions(vec![ExtensionChunk::Extension(
+                ClientExtension::compress_certificate(&[Brotli, Zlib])
+            )])
+            .build()
+            .is_ok());
+
+        let overrider = builder.with_ja3_full("771,4865,27,,").unwrap();
+        let ExtensionChunk::Extension(ext) = &overrider.extensions[0] else {
+            panic!("compress_certificate is not a fixed extension")
+        };
+        assert_eq!(ext.get_encoding()[2..], [0, 5, 4, 0, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_ja3_errors() {
+        let builder = JAOverrideBuilder::default();
+        assert_eq!(
+            builder.with_ja3_full("771,47-53").unwrap_err(),
+            Error::Ja3FieldCount(2)
+        );
+        assert_eq!(
+            builder.with_ja3_full("771,47-x53,0,,").unwrap_err(),
+            Error::InvalidNumber {
+                field: Ja3Field::CipherSuites,
+                index: 1,
+                value: "x53".to_string()
+            }
+        );
+        assert_eq!(
+            builder.with_ja3_full("771,47,0-30032,,").unwrap_err(),
+            Error::UnregisteredExtension {
+                index: 1,
+                extension: 30032
+            }
+        );
+        assert_eq!(
+            builder.with_ja3_full("771,47,0-16,,").unwrap_err(),
+            Error::MissingInput(BuilderInput::ALPN)
+        );
+    }
+
+    #[test]
+    fn test_malformed_extensions() {
+        let decode = |typ: ExtensionType, payload: &[u8]| {
+            decode_raw_extension(
+                &RawExtension {
+                    typ: u16::from(typ),
+                    payload: payload.to_vec(),
+                },
+                None,
+            )
+        };
+        assert!(matches!(
+            decode(ExtensionType::ECPointFormats, &[1, 0, 0]),
+            Err(Error::MalformedHello("trailing bytes in extension"))
+        ));
+        // supported_versions with GREASE after TLS 1.3
+        assert!(matches!(
+            decode(ExtensionType::SupportedVersions, &[4, 3, 4, 0x1a, 0x1a]),
+            Err(Error::MalformedHello(
+                "GREASE value in an unsupported position"
+            ))
+        ));
+        assert!(decode(ExtensionType::SupportedVersions, &[4, 0x1a, 0x1a, 3, 4]).is_ok());
+    }
+
+    #[test]
+    fn test_client_hello_info() {
+        let record = decode_hex(CHROME_HELLO_RECORD);
+        let info = crate::ClientHelloInfo::from_bytes(&record).unwrap();
+        #[cfg(any(feature = "ja3", feature = "ja4"))]
+        let overrider = JAOverrideBuilder::default()
+            .from_client_hello_bytes(&record)
+            .unwrap();
+        assert_eq!(info.cipher_suites.len(), 16);
+        assert_eq!(info.alpn, vec![b"http/1.1".to_vec()]);
+        #[cfg(feature = "ja3")]
+        {
+            assert_eq!(
+                info.ja3_full(),
+                "771,4865-4866-4867-49195-49199-49196-49200-52393-52392-49171-49172-156-157-47-53,0-23-65281-10-11-35-16-5-13-18-51-45-43-27-17513-21,29-23-24,0"
+            );
+            assert_eq!(info.ja3_hash(), "cd08e31494f9531f560d64c695473da9");
+            assert_eq!(info.ja3_full(), overrider.ja3_full());
+        }
+        #[cfg(feature = "ja4")]
+        assert_eq!(info.ja4_hash(), overrider.ja4_hash());
     }
 }
@@ -0,0 +1,672 @@
+use crate::builder::ExtensionChunk;
+use crate::extensions::{self, GreaseSlot, GreaseValues, HelloContext, PaddingMode, SniMode};
+use crate::{JAOverride, TLS_GREASE_VALUES_INT};
+use rand::prelude::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+#[cfg(feature = "connection-hooks")]
+use rustls::client::client_hello::ConnectionHelloOverride;
+use rustls::crypto::ActiveKeyExchange;
+use rustls::internal::msgs::codec::Codec;
+use rustls::internal::msgs::enums::ExtensionType;
+use rustls::internal::msgs::handshake::{ClientExtension, ClientSessionTicket};
+use rustls::{CipherSuite, NamedGroup, ProtocolVersion};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// The ClientHellos of one connection. rustls tells it whether a hello
+/// answers a HelloRetryRequest, and the second one then repeats the first.
+pub(crate) struct ConnectionOverride {
+    cipher_suites: Arc<[CipherSuite]>,
+    extensions: Arc<[ExtensionChunk]>,
+    shuffle_extension: bool,
+    pinned_extensions: Arc<[ExtensionType]>,
+    ech: bool,
+    rng: ChaCha20Rng,
+    /// What the first ClientHello sent.
+    first_cipher_suites: Option<Vec<CipherSuite>>,
+    first_extensions: Option<Vec<ClientExtension>>,
+    /// Secrets of the key shares the first ClientHello added.
+    key_exchanges: Vec<Box<dyn ActiveKeyExchange>>,
+}
+
+impl ConnectionOverride {
+    pub(crate) fn new(overrider: &JAOverride, rng: ChaCha20Rng) -> Self {
+        Self {
+            cipher_suites: overrider.cipher_suites.clone(),
+            extensions: overrider.extensions.clone(),
+            shuffle_extension: overrider.shuffle_extension,
+            pinned_extensions: overrider.pinned_extensions.clone(),
+            ech: overrider.ech_config_list.is_some(),
+            rng,
+            first_cipher_suites: None,
+            first_extensions: None,
+            key_exchanges: Vec::new(),
+        }
+    }
+
+    fn first_hello(&mut self, extensions: &[ClientExtension]) -> Vec<ClientExtension> {
+        // resolved once, generators size themselves from the name actually sent
+        let server_name = extensions::server_name(extensions);
+        let sent_name = self
+            .extensions
+            .iter()
+            .find_map(|chunk| match chunk {
+                ExtensionChunk::Sni(mode) => Some(mode.resolve(server_name.as_deref())),
+                _ => None,
+            })
+            .flatten();
+        let mut cx = HelloContext::new(
+            extensions,
+            sent_name,
+            ChaCha20Rng::from_seed(self.rng.gen()),
+        );
+        let mut res = Vec::with_capacity(self.extensions.len());
+        for ext in self.extensions.iter() {
+            match ext {
+                ExtensionChunk::Grease => {
+                    res.push(cx.next_grease_extension());
+                }
+                ExtensionChunk::Sni(SniMode::Forward) => {
+                    if let Some(sni) = extensions
+                        .iter()
+                        .find(|ext| matches!(ext, ClientExtension::ServerName(_)))
+                    {
+                        res.push(sni.clone());
+                    }
+                }
+                ExtensionChunk::Sni(_) => {
+                    if let Some(name) = cx.server_name() {
+                        res.push(SniMode::extension(name));
+                    }
+                }
+                ExtensionChunk::KeyShare => {
+                    if let Some(key_share) = extensions
+                        .iter()
+                        .find(|ext| matches!(ext, ClientExtension::KeyShare(_)))
+                    {
+                        res.push(key_share.clone());
+                    }
+                }
+                ExtensionChunk::Extension(ext) => {
+                    res.push(ext.clone());
+                }
+                ExtensionChunk::Generated(generator) => {
+                    res.push(generator.generate(&mut cx));
+                }
+                ExtensionChunk::Passthrough(typ) => {
+                    if let Some(ext) = extensions.iter().find(|ext| ext.get_ext_type() == *typ) {
+                        res.push(ext.clone());
+                    }
+                }
+                ExtensionChunk::SessionTicket => {
+                    res.push(
+                        extensions
+                            .iter()
+                            .find(|ext| matches!(ext, ClientExtension::SessionTicket(_)))
+                            .cloned()
+                            .unwrap_or(ClientExtension::SessionTicket(
+                                ClientSessionTicket::Request,
+                            )),
+                    );
+                }
+                ExtensionChunk::Padding(_) => {
+                    // sized in pad, once the hello is encoded
+                    res.push(ClientExtension::padding(vec![]));
+                }
+                ExtensionChunk::GreasedNameGroups(v) => {
+                    let grease = NamedGroup::from(cx.grease(GreaseSlot::Group));
+                    let is_grease = |g: &NamedGroup| TLS_GREASE_VALUES_INT.contains(&u16::from(*g));
+                    let mut groups = Vec::with_capacity(v.len() + 1);
+                    if !v.iter().any(is_grease) {
+                        groups.push(grease);
+                    }
+                    groups.extend(v.iter().map(|g| if is_grease(g) { grease } else { *g }));
+                    res.push(ClientExtension::NamedGroups(groups));
+                }
+                ExtensionChunk::GreasedTLSVersion(v) => {
+                    let mut versions = Vec::with_capacity(v.len() + 1);
+                    versions.push(ProtocolVersion::from(cx.grease(GreaseSlot::Version)));
+                    versions.extend(v.iter().cloned());
+                    res.push(ClientExtension::SupportedVersions(versions));
+                }
+            }
+        }
+        if self.ech {
+            // rustls appends the real one to the outer hello
+            res.retain(|e| e.get_ext_type() != ExtensionType::EncryptedClientHello);
+        }
+        self.place_dynamic_extensions(&mut res, extensions);
+        if self.shuffle_extension {
+            self.shuffle(&mut res, &mut cx.rng);
+        }
+        self.key_exchanges = cx.key_exchanges;
+        res
+    }
+
+    /// Permute the extensions that were actually produced, leaving pinned
+    /// ones in place, like Chrome's extension permutation.
+    fn shuffle(&self, res: &mut [ClientExtension], rng: &mut ChaCha20Rng) {
+        let slots: Vec<usize> = (0..res.len())
+            .filter(|&i| !self.is_pinned(res[i].get_ext_type()))
+            .collect();
+        let mut movable: Vec<ClientExtension> = slots.iter().map(|&i| res[i].clone()).collect();
+        movable.shuffle(rng);
+        for (i, ext) in slots.into_iter().zip(movable) {
+            res[i] = ext;
+        }
+    }
+
+    /// Dropping pre_shared_key, early_data or cookie would turn off
+    /// resumption, 0-RTT or break a retry, so the ones the profile does not
+    /// place go before padding.
+    fn place_dynamic_extensions(
+        &self,
+        res: &mut Vec<ClientExtension>,
+        extensions: &[ClientExtension],
+    ) {
+        for typ in [
+            ExtensionType::EarlyData,
+            ExtensionType::Cookie,
+            ExtensionType::PreSharedKey,
+        ] {
+            if !res.iter().any(|ext| ext.get_ext_type() == typ) {
+                if let Some(ext) = extensions.iter().find(|ext| ext.get_ext_type() == typ) {
+                    let pos = res
+                        .iter()
+                        .position(|e| e.get_ext_type() == ExtensionType::Padding)
+                        .unwrap_or(res.len());
+                    res.insert(pos, ext.clone());
+                }
+            }
+        }
+        // binders are computed over everything before pre_shared_key
+        if let Some(pos) = res
+            .iter()
+            .position(|ext| ext.get_ext_type() == ExtensionType::PreSharedKey)
+        {
+            let psk = res.remove(pos);
+            res.push(psk);
+        }
+    }
+
+    fn padding(&self) -> Option<PaddingMode> {
+        self.extensions.iter().find_map(|chunk| match chunk {
+            ExtensionChunk::Padding(mode) => Some(*mode),
+            _ => None,
+        })
+    }
+
+    fn is_pinned(&self, typ: ExtensionType) -> bool {
+        TLS_GREASE_VALUES_INT.contains(&u16::from(typ))
+            || matches!(typ, ExtensionType::Padding | ExtensionType::PreSharedKey)
+            || self.pinned_extensions.contains(&typ)
+    }
+}
+
+impl Debug for ConnectionOverride {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectionOverride").finish()
+    }
+}
+
+impl ConnectionOverride {
+    pub(crate) fn cipher_suites(&mut self, retry: bool) -> Vec<CipherSuite> {
+        if let (true, Some(first)) = (retry, &self.first_cipher_suites) {
+            return first.clone();
+        }
+        let grease = GreaseValues::new(&mut self.rng).get(GreaseSlot::Cipher);
+        let sent: Vec<CipherSuite> = self
+            .cipher_suites
+            .iter()
+            .map(|c| {
+                if TLS_GREASE_VALUES_INT.contains(&u16::from(*c)) {
+                    CipherSuite::from(grease)
+                } else {
+                    *c
+                }
+            })
+            .collect();
+        self.first_cipher_suites = Some(sent.clone());
+        sent
+    }
+
+    pub(crate) fn extensions(
+        &mut self,
+        extensions: Vec<ClientExtension>,
+        retry: bool,
+    ) -> Vec<ClientExtension> {
+        if let (true, Some(first)) = (retry, &self.first_extensions) {
+            // the server asked for the share rustls now sends
+            self.key_exchanges.clear();
+            let mut res = retry_extensions(first, &extensions);
+            self.place_dynamic_extensions(&mut res, &extensions);
+            if self.padding() == Some(PaddingMode::BoringSsl) {
+                // BoringSSL only pads the first hello
+                res.retain(|ext| ext.get_ext_type() != ExtensionType::Padding);
+            }
+            return res;
+        }
+        let res = self.first_hello(&extensions);
+        self.first_extensions = Some(res.clone());
+        res
+    }
+
+    /// Without the per-connection hooks rustls asks for the extensions of
+    /// every hello on its own and never reports the encoded length, so
+    /// padding assumes the 32-byte session id rustls sends.
+    pub(crate) fn single_hello(mut self, extensions: Vec<ClientExtension>) -> Vec<ClientExtension> {
+        let mut res = self.extensions(extensions, false);
+        let extensions_len: usize = res.iter().map(|e| e.get_encoding().len()).sum();
+        // header, version, random, session id, cipher suites, compression
+        let hello_len =
+            4 + 2 + 32 + (1 + 32) + (2 + 2 * self.cipher_suites.len()) + 2 + (2 + extensions_len);
+        self.pad(&mut res, hello_len);
+        res
+    }
+
+    /// `hello_len` is the encoded length of the hello, padding included.
+    pub(crate) fn pad(&self, extensions: &mut Vec<ClientExtension>, hello_len: usize) {
+        let Some(mode) = self.padding() else {
+            return;
+        };
+        let Some(pos) = extensions
+            .iter()
+            .position(|e| e.get_ext_type() == ExtensionType::Padding)
+        else {
+            return;
+        };
+        let unpadded = hello_len - extensions[pos].get_encoding().len();
+        match mode.payload_len(unpadded) {
+            Some(len) => extensions[pos] = ClientExtension::padding(vec![0; len]),
+            None => {
+                extensions.remove(pos);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "connection-hooks")]
+impl ConnectionHelloOverride for ConnectionOverride {
+    fn override_cipher_suites(
+        &mut self,
+        _cipher_suites: Vec<CipherSuite>,
+        retry: bool,
+    ) -> Vec<CipherSuite> {
+        self.cipher_suites(retry)
+    }
+
+    fn override_extensions(
+        &mut self,
+        extensions: Vec<ClientExtension>,
+        retry: bool,
+    ) -> Vec<ClientExtension> {
+        self.extensions(extensions, retry)
+    }
+
+    /// rustls passes the length of the (outer) hello as it will be sent, ECH
+    /// included, so padding is sized from the real session id and extensions.
+    fn finish_extensions(&mut self, extensions: &mut Vec<ClientExtension>, hello_len: usize) {
+        self.pad(extensions, hello_len);
+    }
+
+    fn key_exchange(&mut self, group: NamedGroup) -> Option<Box<dyn ActiveKeyExchange>> {
+        let pos = self
+            .key_exchanges
+            .iter()
+            .position(|kx| kx.group() == group)?;
+        Some(self.key_exchanges.swap_remove(pos))
+    }
+}
+
+/// The second ClientHello repeats the first one, order and GREASE values
+/// included, except for what rustls changes after a HelloRetryRequest: the
+/// key share for the selected group, the cookie, dropped early data and a
+/// repeated ECH extension. The server name stays as sent, it may differ from
+/// rustls's.
+fn retry_extensions(
+    first: &[ClientExtension],
+    extensions: &[ClientExtension],
+) -> Vec<ClientExtension> {
+    let current = |typ: ExtensionType| {
+        extensions
+            .iter()
+            .find(|ext| ext.get_ext_type() == typ)
+            .cloned()
+    };
+    let mut res = Vec::with_capacity(first.len() + 1);
+    for ext in first {
+        match ext.get_ext_type() {
+            typ @ (ExtensionType::KeyShare
+            | ExtensionType::PreSharedKey
+            | ExtensionType::EarlyData
+            | ExtensionType::Cookie
+            | ExtensionType::SessionTicket) => res.extend(current(typ)),
+            _ => res.push(ext.clone()),
+        }
+    }
+    // after a rejected ECH offer, rustls repeats the outer extension itself
+    if !res
+        .iter()
+        .any(|ext| ext.get_ext_type() == ExtensionType::EncryptedClientHello)
+    {
+        res.extend(current(ExtensionType::EncryptedClientHello));
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::{JAOverrideBuilder, GREASE_CIPHER_SUITE};
+    #[cfg(feature = "ja4")]
+    use crate::error::Error;
+    use crate::test_util::*;
+    use rustls::SignatureScheme;
+
+    #[test]
+    fn test_grease_cipher_suite() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        let overrider = JAOverrideBuilder::default()
+            .with_grease(true)
+            .with_ja3_full("771,4865-4866,0-23-10-11,29,0")
+            .unwrap();
+        assert_eq!(overrider.cipher_suites[0], GREASE_CIPHER_SUITE);
+        let sent = overrider.override_cipher_suites(vec![]);
+        assert_eq!(sent.len(), 3);
+        assert!(TLS_GREASE_VALUES_INT.contains(&u16::from(sent[0])));
+        assert_eq!(&sent[1..], &overrider.cipher_suites[1..]);
+        #[cfg(feature = "ja3")]
+        assert_eq!(overrider.ja3_full(), "771,4865-4866,0-23-10-11,29,0");
+        #[cfg(feature = "ja4")]
+        assert!(overrider.ja4_hash().starts_with("t12d0204"));
+    }
+
+    #[test]
+    fn test_padding() {
+        use rustls::internal::msgs::codec::Codec;
+        let padded_len = |padding: PaddingMode, unpadded: usize| {
+            let mut conn = JAOverrideBuilder::default()
+                .with_cipher_suites(vec![CipherSuite::TLS13_AES_128_GCM_SHA256])
+                .push_extension(ExtensionChunk::Padding(padding))
+                .build()
+                .unwrap()
+                .connection();
+            let mut sent = conn.extensions(vec![], false);
+            // rustls encodes the hello with the empty placeholder
+            conn.pad(&mut sent, unpadded + 4);
+            sent.iter()
+                .find(|e| e.get_ext_type() == ExtensionType::Padding)
+                .map(|e| unpadded + e.get_encoding().len())
+        };
+        assert_eq!(padded_len(PaddingMode::BoringSsl, 300), Some(512));
+        assert_eq!(padded_len(PaddingMode::BoringSsl, 400), Some(512));
+        assert_eq!(padded_len(PaddingMode::BoringSsl, 213), None);
+        assert_eq!(padded_len(PaddingMode::BoringSsl, 613), None);
+        assert_eq!(padded_len(PaddingMode::To(600), 300), Some(600));
+        assert_eq!(padded_len(PaddingMode::To(600), 717), Some(721));
+        // too short for the extension header
+        assert_eq!(PaddingMode::BoringSsl.payload_len(0x1fe), Some(1));
+
+        // sized from the hello rustls sends, ECH included, which only the
+        // hooks report
+        let ech_modes: &[bool] = if cfg!(feature = "connection-hooks") {
+            &[false, true]
+        } else {
+            &[false]
+        };
+        for &ech in ech_modes {
+            let mut builder = JAOverrideBuilder::default();
+            if ech {
+                builder.with_ech_config_list(decode_hex(ECH_CONFIG_LIST));
+            }
+            let overrider = builder
+                .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+                .with_signature_algorithms(vec![SignatureScheme::ECDSA_NISTP256_SHA256])
+                .with_padding(PaddingMode::To(1000))
+                .with_ja3_full("771,4865,0-10-13-43-51-21,29,")
+                .unwrap();
+            // the server does not support ECH and rejects it
+            let hellos = if ech {
+                try_handshake(overrider, &[NamedGroup::X25519]).0
+            } else {
+                handshake(overrider, &[NamedGroup::X25519]).0
+            };
+            assert_eq!(hello_len(&hellos[0]), 1000);
+            assert_eq!(
+                hellos[0]
+                    .extensions
+                    .iter()
+                    .any(|e| e.typ == u16::from(ExtensionType::EncryptedClientHello)),
+                ech
+            );
+        }
+    }
+
+    #[test]
+    fn test_passthrough() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        use rustls::internal::msgs::base::PayloadU16;
+        use rustls::internal::msgs::codec::{Codec, Reader};
+        let psk = ClientExtension::read(&mut Reader::init(&decode_hex(concat!(
+            "0029002d",
+            "000800026162000000000021",
+            "200000000000000000000000000000000000000000000000000000000000000000",
+        ))))
+        .unwrap();
+        let resumed = vec![
+            psk,
+            ClientExtension::EarlyData,
+            ClientExtension::Cookie(PayloadU16::new(vec![1, 2, 3])),
+        ];
+        let types = |overrider: &JAOverride, extensions: Vec<ClientExtension>| {
+            overrider
+                .override_extensions(extensions)
+                .iter()
+                .map(|e| u16::from(e.get_ext_type()))
+                .collect::<Vec<_>>()
+        };
+
+        // pre_shared_key is moved after padding
+        let overrider = JAOverrideBuilder::default()
+            .with_shuffle_extension(true)
+            .with_padding(PaddingMode::To(512))
+            .with_ja3_full("771,4865,42-41-23-65281-21,,")
+            .unwrap();
+        for _ in 0..16 {
+            let sent = types(&overrider, resumed.clone());
+            assert_eq!(sent.len(), 6);
+            assert_eq!(sent[4..], [21, 41]);
+        }
+        // nothing to forward on a first connection
+        let sent = types(&overrider, vec![]);
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[2], 21);
+
+        // not in the profile, but still sent
+        let overrider = JAOverrideBuilder::default()
+            .with_ja3_full("771,4865,23,,")
+            .unwrap();
+        assert_eq!(types(&overrider, resumed), [23, 42, 44, 41]);
+    }
+
+    #[test]
+    fn test_session_ticket() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        use rustls::internal::msgs::base::Payload;
+        use rustls::internal::msgs::handshake::ClientSessionTicket;
+        let overrider = JAOverrideBuilder::default()
+            .with_ja3_full("771,49195,35-23,,")
+            .unwrap();
+        let offer =
+            ClientExtension::SessionTicket(ClientSessionTicket::Offer(Payload::new(vec![5; 64])));
+        let sent = overrider.override_extensions(vec![offer.clone()]);
+        assert_eq!(format!("{:?}", sent[0]), format!("{:?}", offer));
+        let sent = overrider.override_extensions(vec![]);
+        assert!(matches!(
+            sent[0],
+            ClientExtension::SessionTicket(ClientSessionTicket::Request)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "connection-hooks")]
+    fn test_hello_retry_request() {
+        use crate::parse::RawClientHello;
+        let overrider = JAOverrideBuilder::default()
+            .with_shuffle_extension(true)
+            .from_client_hello_bytes(&decode_hex(CHROME_HELLO_RECORD))
+            .unwrap();
+        // the client offers X25519 first, the server only accepts P-256
+        let (hellos, _) = handshake(overrider, &[NamedGroup::secp256r1]);
+        assert_eq!(hellos.len(), 2);
+        let (first, second) = (&hellos[0], &hellos[1]);
+        // GREASE cipher suite included
+        assert_eq!(first.cipher_suites, second.cipher_suites);
+        let types =
+            |hello: &RawClientHello| hello.extensions.iter().map(|e| e.typ).collect::<Vec<_>>();
+        // like BoringSSL, only the first hello is padded
+        let padding = u16::from(ExtensionType::Padding);
+        assert!(types(first).contains(&padding));
+        let mut unpadded = types(first);
+        unpadded.retain(|&typ| typ != padding);
+        assert_eq!(unpadded, types(second));
+        for (a, b) in first
+            .extensions
+            .iter()
+            .filter(|e| e.typ != padding)
+            .zip(second.extensions.iter())
+        {
+            if a.typ != u16::from(ExtensionType::KeyShare) {
+                assert_eq!(a, b);
+            }
+        }
+        let key_share = second
+            .extensions
+            .iter()
+            .find(|e| e.typ == u16::from(ExtensionType::KeyShare))
+            .unwrap();
+        // one P-256 entry
+        assert_eq!(key_share.payload.len(), 2 + 4 + 65);
+        assert_eq!(key_share.payload[2..4], [0, 0x17]);
+    }
+
+    #[test]
+    #[cfg(feature = "connection-hooks")]
+    fn test_key_share_selected() {
+        let overrider = JAOverride::builder()
+            .with_cipher_suites(vec![CipherSuite::TLS13_AES_128_GCM_SHA256])
+            .push_extension(ExtensionChunk::sni())
+            .push_extension(ExtensionChunk::named_groups(
+                vec![NamedGroup::X25519, NamedGroup::secp256r1],
+                true,
+            ))
+            .push_extension(ExtensionChunk::signature_algorithms(vec![
+                SignatureScheme::ECDSA_NISTP256_SHA256,
+            ]))
+            .push_extension(ExtensionChunk::key_share(
+                true,
+                vec![NamedGroup::X25519, NamedGroup::secp256r1],
+            ))
+            .push_extension(ExtensionChunk::tls_versions(
+                vec![ProtocolVersion::TLSv1_3],
+                true,
+            ))
+            .build()
+            .unwrap();
+        // rustls offers X25519, the server selects the added P-256 share
+        // without a retry
+        let (hellos, _) = handshake(overrider, &[NamedGroup::secp256r1]);
+        assert_eq!(hellos.len(), 1);
+    }
+
+    #[cfg(feature = "ja4")]
+    #[test]
+    fn test_sni_modes() {
+        use crate::extensions::SniHook;
+        use crate::ClientHelloInfo;
+        use rustls::pki_types::DnsName;
+
+        let omit_localhost: SniHook = Arc::new(|name| {
+            name.filter(|n| *n != "localhost")
+                .and_then(|n| DnsName::try_from(n.to_string()).ok())
+        });
+        for (mode, expected) in [
+            (SniMode::Forward, Some("localhost")),
+            (
+                SniMode::override_name("internal.test").unwrap(),
+                Some("internal.test"),
+            ),
+            (SniMode::Omit, None),
+            (SniMode::Hook(omit_localhost), None),
+        ] {
+            let overrider = JAOverrideBuilder::default()
+                .with_sni(mode)
+                .from_client_hello_bytes(&decode_hex(CHROME_HELLO_RECORD))
+                .unwrap();
+            let ja4 = overrider.ja4_hash_for(Some("localhost"));
+            let (hellos, server_name) = handshake(overrider, &[NamedGroup::X25519]);
+            assert_eq!(server_name.as_deref(), expected);
+            let sent = ClientHelloInfo::from_raw(&hellos[0]).unwrap().ja4_hash();
+            assert_eq!(ja4[..4], sent[..4]);
+            assert_eq!(&ja4[3..4], if expected.is_some() { "d" } else { "i" });
+        }
+
+        let overrider = JAOverrideBuilder::default()
+            .with_sni(SniMode::Omit)
+            .from_client_hello_bytes(&decode_hex(CHROME_HELLO_RECORD))
+            .unwrap();
+        assert_eq!(&overrider.ja4_hash()[..4], "t13i");
+
+        for name in ["", "bad name", "a..b", &"a".repeat(254), &"a".repeat(65533)] {
+            assert_eq!(
+                SniMode::override_name(name).unwrap_err(),
+                Error::InvalidServerName(name.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_seeded_override() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        let hello = |seed| {
+            JAOverrideBuilder::default()
+                .with_grease(true)
+                .with_shuffle_extension(true)
+                .with_seed(seed)
+                .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+                .with_ja3_full("771,4865,0-23-65281-10-11-35-43-65037,29-23-24,0")
+                .unwrap()
+                .override_extensions(vec![])
+        };
+        assert_eq!(format!("{:?}", hello(7)), format!("{:?}", hello(7)));
+        assert_ne!(format!("{:?}", hello(7)), format!("{:?}", hello(8)));
+    }
+
+    #[test]
+    fn test_shuffle_pinned() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        let ja = JAOverrideBuilder::default()
+            .with_grease(true)
+            .with_shuffle_extension(true)
+            .with_pinned_extension(ExtensionType::ExtendedMasterSecret)
+            .with_padding(PaddingMode::To(512))
+            .with_seed(1)
+            .with_ja3_full("771,4865,0-23-65281-10-11-35-51-21,29-23-24,0")
+            .unwrap();
+        // no SNI or key share offered, as when connecting to an IP address;
+        // the key share still carries the GREASE entry
+        for _ in 0..32 {
+            let types: Vec<_> = ja
+                .override_extensions(vec![])
+                .iter()
+                .map(|e| u16::from(e.get_ext_type()))
+                .collect();
+            assert_eq!(types.len(), 9);
+            assert!(TLS_GREASE_VALUES_INT.contains(&types[0]));
+            assert_eq!(types[1], 23);
+            assert!(TLS_GREASE_VALUES_INT.contains(&types[7]));
+            assert_eq!(types[8], 21);
+        }
+    }
+}
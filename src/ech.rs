@@ -0,0 +1,176 @@
+use crate::JAOverride;
+use rustls::client::{EchConfig, EchMode};
+use rustls::crypto::hpke::Hpke;
+use rustls::internal::msgs::codec::Codec;
+use rustls::pki_types::EchConfigListBytes;
+use rustls::PeerIncompatible;
+use std::sync::Mutex;
+
+impl JAOverride {
+    /// The mode to pass to `ConfigBuilder::with_ech`.
+    ///
+    /// With an ECHConfigList, rustls offers ECH and appends the real ECH
+    /// extension to the outer hello, which otherwise follows the profile. Without one this returns `None` and the
+    /// profile's GREASE ECH is sent instead.
+    pub fn ech_mode(
+        &self,
+        hpke_suites: &[&'static dyn Hpke],
+    ) -> Result<Option<EchMode>, rustls::Error> {
+        match &self.ech_config_list {
+            Some(list) => Ok(Some(EchMode::Enable(EchConfig::new(
+                list.clone(),
+                hpke_suites,
+            )?))),
+            None => Ok(None),
+        }
+    }
+
+    /// The override to reconnect with after `err`, if the server rejected ECH
+    /// and sent retry configs: the same profile with those configs as its
+    /// ECHConfigList. A connection made with it must not be retried again.
+    pub fn retry_with_ech_configs(&self, err: &rustls::Error) -> Option<JAOverride> {
+        Some(JAOverride {
+            legacy_version: self.legacy_version,
+            cipher_suites: self.cipher_suites.clone(),
+            extensions: self.extensions.clone(),
+            shuffle_extension: self.shuffle_extension,
+            pinned_extensions: self.pinned_extensions.clone(),
+            ech_config_list: Some(retry_configs(err)?),
+            rng: Mutex::new(self.hello_rng()),
+        })
+    }
+}
+
+/// The retry configs a server sent when rejecting ECH, see
+/// [`JAOverride::retry_with_ech_configs`].
+pub fn retry_configs(err: &rustls::Error) -> Option<EchConfigListBytes<'static>> {
+    match err {
+        rustls::Error::PeerIncompatible(PeerIncompatible::ServerRejectedEncryptedClientHello(
+            Some(configs),
+        )) => Some(EchConfigListBytes::from(configs.get_encoding())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::builder::JAOverrideBuilder;
+    use crate::parse::RawClientHello;
+    use crate::test_util::*;
+    use crate::TLS_GREASE_VALUES_INT;
+    use rustls::internal::msgs::enums::ExtensionType;
+    use rustls::{NamedGroup, ProtocolVersion, SignatureScheme};
+
+    #[test]
+    fn test_ech_config_list() {
+        use crate::ech::retry_configs;
+        use rustls::client::client_hello::ClientHelloOverride;
+        use rustls::internal::msgs::codec::{Codec, Reader};
+        use rustls::internal::msgs::handshake::EchConfigPayload;
+        use rustls::PeerIncompatible;
+        let list = decode_hex(ECH_CONFIG_LIST);
+        let ja3 = "771,4865,0-65037-43,,";
+        let overrider = JAOverrideBuilder::default()
+            .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+            .with_ech_config_list(list.clone())
+            .with_ja3_full(ja3)
+            .unwrap();
+        let sent = overrider.override_extensions(vec![]);
+        assert!(sent
+            .iter()
+            .all(|e| e.get_ext_type() != ExtensionType::EncryptedClientHello));
+        // no HPKE suite available
+        assert!(overrider.ech_mode(&[]).is_err());
+
+        let fallback = JAOverrideBuilder::default()
+            .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+            .with_ja3_full(ja3)
+            .unwrap();
+        assert!(fallback.ech_mode(&[]).unwrap().is_none());
+        // no SNI offered, so GREASE ECH comes first
+        assert_eq!(
+            fallback.override_extensions(vec![])[0].get_ext_type(),
+            ExtensionType::EncryptedClientHello
+        );
+
+        let configs = Vec::<EchConfigPayload>::read(&mut Reader::init(&list)).unwrap();
+        let rejected = rustls::Error::PeerIncompatible(
+            PeerIncompatible::ServerRejectedEncryptedClientHello(Some(configs)),
+        );
+        assert_eq!(retry_configs(&rejected).unwrap().as_ref(), &list[..]);
+        assert!(retry_configs(&rustls::Error::HandshakeNotComplete).is_none());
+    }
+
+    #[test]
+    fn test_ech_handshake() {
+        use rustls::internal::msgs::codec::{Codec, Reader};
+        use rustls::internal::msgs::handshake::EchConfigPayload;
+        use rustls::PeerIncompatible;
+        let ech_config_id = |hello: &RawClientHello| {
+            let ech = hello
+                .extensions
+                .iter()
+                .find(|e| e.typ == u16::from(ExtensionType::EncryptedClientHello))
+                .unwrap();
+            // outer, kdf, aead, config id
+            ech.payload[5]
+        };
+        let overrider = JAOverrideBuilder::default()
+            .with_grease(true)
+            .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+            .with_signature_algorithms(vec![SignatureScheme::ECDSA_NISTP256_SHA256])
+            .with_ech_config_list(decode_hex(ECH_CONFIG_LIST))
+            .with_ja3_full("771,4865,0-10-13-43-51-65037,29-23,")
+            .unwrap();
+        // the server only reads the outer hello, which follows the profile
+        // with the public name, then rejects ECH without retry configs
+        let (hellos, server_name, result) = try_handshake(overrider, &[NamedGroup::X25519]);
+        assert_eq!(hellos.len(), 1);
+        assert_eq!(server_name.as_deref(), Some("public.example"));
+        let types: Vec<u16> = hellos[0].extensions.iter().map(|e| e.typ).collect();
+        // rustls appends the real ECH extension
+        assert!(TLS_GREASE_VALUES_INT.contains(&types[0]));
+        assert_eq!(types[1..6], [0, 10, 13, 43, 51]);
+        assert!(TLS_GREASE_VALUES_INT.contains(&types[6]));
+        assert_eq!(types[7..], [65037]);
+        assert_eq!(ech_config_id(&hellos[0]), 0x2a);
+        let err = result.unwrap_err();
+        assert_eq!(
+            err,
+            rustls::Error::PeerIncompatible(PeerIncompatible::ServerRejectedEncryptedClientHello(
+                None
+            ))
+        );
+        let overrider = JAOverrideBuilder::default()
+            .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+            .with_signature_algorithms(vec![SignatureScheme::ECDSA_NISTP256_SHA256])
+            .with_ech_config_list(decode_hex(ECH_CONFIG_LIST))
+            .with_ja3_full("771,4865,0-10-13-43-51-65037,29-23,")
+            .unwrap();
+        assert!(overrider.retry_with_ech_configs(&err).is_none());
+
+        // retry configs, here with another config id, are used on reconnect
+        let mut list = decode_hex(ECH_CONFIG_LIST);
+        list[6] = 0x2b;
+        let configs = Vec::<EchConfigPayload>::read(&mut Reader::init(&list)).unwrap();
+        let rejected = rustls::Error::PeerIncompatible(
+            PeerIncompatible::ServerRejectedEncryptedClientHello(Some(configs)),
+        );
+        let retry = overrider.retry_with_ech_configs(&rejected).unwrap();
+        assert_eq!(retry.ech_config_list.as_ref().unwrap().as_ref(), &list[..]);
+        let (hellos, _, result) = try_handshake(retry, &[NamedGroup::X25519]);
+        assert_eq!(hellos.len(), 1);
+        assert_eq!(ech_config_id(&hellos[0]), 0x2b);
+        assert!(result.is_err());
+
+        // the HelloRetryRequest for P-256 keeps ECH in the second hello
+        #[cfg(feature = "connection-hooks")]
+        {
+            let retry = overrider.retry_with_ech_configs(&rejected).unwrap();
+            let (hellos, _, result) = try_handshake(retry, &[NamedGroup::secp256r1]);
+            assert_eq!(hellos.len(), 2);
+            assert!(hellos.iter().all(|hello| ech_config_id(hello) == 0x2b));
+            assert!(result.is_err());
+        }
+    }
+}
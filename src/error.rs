@@ -0,0 +1,115 @@
+use rustls::CertificateCompressionAlgorithm;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A JA3 string must have exactly five comma-separated fields.
+    Ja3FieldCount(usize),
+    /// A JA3 token is not a number of the expected width.
+    InvalidNumber {
+        field: Ja3Field,
+        index: usize,
+        value: String,
+    },
+    /// The JA3 string lists an extension that cannot be generated and has no
+    /// payload in `JAOverrideBuilder::unknown_extensions`.
+    UnregisteredExtension {
+        index: usize,
+        extension: u16,
+    },
+    /// An extension listed in the JA3 string needs an input the builder was not
+    /// given.
+    MissingInput(BuilderInput),
+    /// A certificate compression algorithm is advertised but the rustls
+    /// configuration cannot decompress it.
+    NoDecompressor(CertificateCompressionAlgorithm),
+    /// A server name to send that is not a valid DNS name.
+    InvalidServerName(String),
+    /// A length range without any allowed length in it.
+    EmptyRange {
+        min: usize,
+        max: usize,
+    },
+    MalformedHello(&'static str),
+    MalformedCapture(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ja3Field {
+    Version,
+    CipherSuites,
+    Extensions,
+    NamedGroups,
+    ECPointFormats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuilderInput {
+    TLSVersions,
+    ALPN,
+    ALPS,
+    SignatureAlgorithms,
+    CompressCertificate,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Ja3FieldCount(n) => {
+                write!(f, "JA3 string has {} fields, expected 5", n)
+            }
+            Error::InvalidNumber {
+                field,
+                index,
+                value,
+            } => write!(
+                f,
+                "invalid number {:?} at position {} of JA3 {}",
+                value, index, field
+            ),
+            Error::UnregisteredExtension { index, extension } => write!(
+                f,
+                "extension {} at position {} of JA3 extensions has no payload registered",
+                extension, index
+            ),
+            Error::MissingInput(input) => {
+                write!(f, "builder input {} is required but not set", input)
+            }
+            Error::NoDecompressor(algorithm) => {
+                write!(f, "no certificate decompressor for {:?}", algorithm)
+            }
+            Error::InvalidServerName(name) => write!(f, "invalid server name {:?}", name),
+            Error::EmptyRange { min, max } => {
+                write!(f, "empty range {}..={}", min, max)
+            }
+            Error::MalformedHello(reason) => write!(f, "malformed hello: {}", reason),
+            Error::MalformedCapture(reason) => write!(f, "malformed capture: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Display for Ja3Field {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Ja3Field::Version => "version",
+            Ja3Field::CipherSuites => "cipher suites",
+            Ja3Field::Extensions => "extensions",
+            Ja3Field::NamedGroups => "named groups",
+            Ja3Field::ECPointFormats => "EC point formats",
+        })
+    }
+}
+
+impl Display for BuilderInput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BuilderInput::TLSVersions => "tls_versions",
+            BuilderInput::ALPN => "alpn",
+            BuilderInput::ALPS => "alps",
+            BuilderInput::SignatureAlgorithms => "signature_algorithms",
+            BuilderInput::CompressCertificate => "compress_certificate",
+        })
+    }
+}
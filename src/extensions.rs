@@ -1,25 +1,744 @@
-use rustls::internal::msgs::enums::ExtensionType;
-use rustls::internal::msgs::enums::HpkeAead::AES_128_GCM;
-use rustls::internal::msgs::enums::HpkeKdf::HKDF_SHA256;
-use rustls::internal::msgs::handshake::ClientExtension;
-
-pub fn grease_ech() -> ClientExtension {
-    let mut payload = Vec::with_capacity(200);
-    // Outer Client Hello
-    payload.push(0);
-    payload.extend_from_slice(&u16::from(HKDF_SHA256).to_be_bytes());
-    payload.extend_from_slice(&u16::from(AES_128_GCM).to_be_bytes());
-    // Config Id
-    payload.push(rand::random());
-    // Enc Length+Payload
-    payload.extend_from_slice(&32u16.to_be_bytes());
-    for _ in 0..32 {
-        payload.push(rand::random());
-    }
-    // Payload Length+Payload
-    payload.extend_from_slice(&144u16.to_be_bytes());
-    for _ in 0..144 {
-        payload.push(rand::random());
-    }
-    ClientExtension::unknown(ExtensionType::Unknown(65037), payload)
+use crate::builder::ExtensionChunk;
+use crate::error::Error;
+use crate::TLS_GREASE_VALUES_INT;
+use rand::Rng;
+use rand_chacha::ChaCha20Rng;
+use rustls::crypto::{ActiveKeyExchange, CryptoProvider};
+use rustls::internal::msgs::codec::Codec;
+use rustls::internal::msgs::enums::{ExtensionType, HpkeAead, HpkeKdf, HpkeKem};
+use rustls::internal::msgs::handshake::{ClientExtension, KeyShareEntry};
+use rustls::pki_types::DnsName;
+use rustls::NamedGroup;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// What a generator may look at when producing its extension.
+pub struct HelloContext<'a> {
+    pub(crate) extensions: &'a [ClientExtension],
+    server_name: Option<String>,
+    pub(crate) rng: ChaCha20Rng,
+    pub(crate) grease: GreaseValues,
+    grease_extensions: Vec<u16>,
+    /// Secrets of the shares [`KeyShares`] generated, handed to rustls when
+    /// the server selects one of their groups.
+    pub(crate) key_exchanges: Vec<Box<dyn ActiveKeyExchange>>,
+}
+
+impl<'a> HelloContext<'a> {
+    pub(crate) fn new(
+        extensions: &'a [ClientExtension],
+        server_name: Option<String>,
+        mut rng: ChaCha20Rng,
+    ) -> Self {
+        Self {
+            extensions,
+            server_name,
+            grease: GreaseValues::new(&mut rng),
+            rng,
+            grease_extensions: Vec::new(),
+            key_exchanges: Vec::new(),
+        }
+    }
+
+    /// The host name this ClientHello sends in its SNI extension, if any,
+    /// which may differ from rustls's under [`SniMode`].
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    pub fn server_name_len(&self) -> Option<usize> {
+        self.server_name.as_ref().map(String::len)
+    }
+
+    /// The GREASE value of `slot` for this ClientHello.
+    pub fn grease(&self, slot: GreaseSlot) -> u16 {
+        self.grease.get(slot)
+    }
+
+    /// The next GREASE extension. The first two follow BoringSSL, which sends
+    /// the second one with a single zero byte; any further one is empty and
+    /// picks its type among the values not used yet.
+    pub(crate) fn next_grease_extension(&mut self) -> ClientExtension {
+        let value = match self.grease_extensions.len() {
+            0 => self.grease(GreaseSlot::Extension1),
+            1 => self.grease(GreaseSlot::Extension2),
+            _ => {
+                let unused: Vec<u16> = TLS_GREASE_VALUES_INT
+                    .iter()
+                    .copied()
+                    .filter(|v| !self.grease_extensions.contains(v))
+                    .collect();
+                unused[self.rng.gen_range(0..unused.len())]
+            }
+        };
+        let payload = match self.grease_extensions.len() {
+            1 => vec![0],
+            _ => vec![],
+        };
+        self.grease_extensions.push(value);
+        ClientExtension::unknown(ExtensionType::Unknown(value), payload)
+    }
+
+    /// Extensions rustls built for this ClientHello before the override.
+    pub fn original_extensions(&self) -> &'a [ClientExtension] {
+        self.extensions
+    }
+
+    /// Randomness for this ClientHello, derived from the override's seed.
+    pub fn rng(&mut self) -> &mut ChaCha20Rng {
+        &mut self.rng
+    }
+}
+
+/// Positions of a ClientHello that get their own GREASE value, as in
+/// BoringSSL's `ssl_grease_index_t`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GreaseSlot {
+    Cipher,
+    /// Shared by supported_groups and key_share.
+    Group,
+    Extension1,
+    Extension2,
+    Version,
+}
+
+/// GREASE values of one ClientHello, derived like BoringSSL does so that the
+/// two GREASE extensions differ.
+#[derive(Debug, Clone, Copy)]
+pub struct GreaseValues {
+    seed: [u8; 5],
+}
+
+impl GreaseValues {
+    pub fn new(rng: &mut impl Rng) -> Self {
+        Self { seed: rng.gen() }
+    }
+
+    pub fn get(&self, slot: GreaseSlot) -> u16 {
+        let value = Self::expand(self.seed[slot as usize]);
+        if slot == GreaseSlot::Extension2 && value == self.get(GreaseSlot::Extension1) {
+            value ^ 0x1010
+        } else {
+            value
+        }
+    }
+
+    fn expand(seed: u8) -> u16 {
+        let b = (seed & 0xf0) | 0x0a;
+        u16::from_be_bytes([b, b])
+    }
+}
+
+/// Produces a fresh extension for every ClientHello.
+pub trait ExtensionGenerator: Send + Sync {
+    /// Type of the generated extension, used for fingerprinting.
+    fn ext_type(&self) -> ExtensionType;
+
+    fn generate(&self, cx: &mut HelloContext<'_>) -> ClientExtension;
+}
+
+struct FnGenerator<F> {
+    ext_type: ExtensionType,
+    f: F,
+}
+
+impl<F> ExtensionGenerator for FnGenerator<F>
+where
+    F: Fn(&mut HelloContext<'_>) -> ClientExtension + Send + Sync,
+{
+    fn ext_type(&self) -> ExtensionType {
+        self.ext_type
+    }
+
+    fn generate(&self, cx: &mut HelloContext<'_>) -> ClientExtension {
+        (self.f)(cx)
+    }
+}
+
+impl ExtensionChunk {
+    pub fn generator(generator: impl ExtensionGenerator + 'static) -> Self {
+        ExtensionChunk::Generated(Arc::new(generator))
+    }
+
+    /// Wrap a closure called for every ClientHello. It must always return an
+    /// extension of `ext_type`.
+    pub fn from_fn<F>(ext_type: ExtensionType, f: F) -> Self
+    where
+        F: Fn(&mut HelloContext<'_>) -> ClientExtension + Send + Sync + 'static,
+    {
+        Self::generator(FnGenerator { ext_type, f })
+    }
+}
+
+/// How [`ExtensionChunk::Padding`] sizes its payload. Lengths count the
+/// ClientHello handshake message, header included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PaddingMode {
+    /// Pad hellos of 256 to 511 bytes to 512 and omit the extension otherwise,
+    /// as BoringSSL does. The hello answering a HelloRetryRequest is not
+    /// padded.
+    #[default]
+    BoringSsl,
+    /// Pad to at least this many bytes, with an empty payload if the hello is
+    /// already longer.
+    To(usize),
+}
+
+impl PaddingMode {
+    /// Payload length for a hello of `unpadded` bytes without the padding
+    /// extension, or `None` if the extension should be left out.
+    pub fn payload_len(&self, unpadded: usize) -> Option<usize> {
+        const EXT_HEADER_LEN: usize = 4;
+        match *self {
+            PaddingMode::BoringSsl => {
+                if unpadded > 0xff && unpadded < 0x200 {
+                    let len = 0x200 - unpadded;
+                    Some(if len > EXT_HEADER_LEN {
+                        len - EXT_HEADER_LEN
+                    } else {
+                        1
+                    })
+                } else {
+                    None
+                }
+            }
+            PaddingMode::To(target) => Some(target.saturating_sub(unpadded + EXT_HEADER_LEN)),
+        }
+    }
+}
+
+/// Picks the name a connection sends, from the name rustls verifies the
+/// certificate against (`None` when connecting to an IP address). Returning
+/// `None` leaves out the server_name extension.
+pub type SniHook = Arc<dyn Fn(Option<&str>) -> Option<DnsName<'static>> + Send + Sync>;
+
+/// What [`ExtensionChunk::Sni`] sends. The certificate is always verified
+/// against the name given to rustls.
+#[derive(Clone, Default)]
+pub enum SniMode {
+    /// The name given to rustls.
+    #[default]
+    Forward,
+    /// Another host name, e.g. for routing, see [`SniMode::override_name`].
+    Override(DnsName<'static>),
+    /// No server_name extension.
+    Omit,
+    /// Decided per connection.
+    Hook(SniHook),
+}
+
+impl SniMode {
+    /// Send `name` instead of rustls's, if it is a valid DNS name.
+    pub fn override_name(name: &str) -> Result<Self, Error> {
+        DnsName::try_from(name)
+            .map(|name| SniMode::Override(name.to_owned()))
+            .map_err(|_| Error::InvalidServerName(name.to_string()))
+    }
+
+    /// The host name sent for a connection to `server_name`, or `None` if the
+    /// extension is left out.
+    pub fn resolve(&self, server_name: Option<&str>) -> Option<String> {
+        match self {
+            SniMode::Forward => server_name.map(str::to_string),
+            SniMode::Override(name) => Some(name.as_ref().to_string()),
+            SniMode::Omit => None,
+            SniMode::Hook(hook) => hook(server_name).map(|name| name.as_ref().to_string()),
+        }
+    }
+
+    /// The server_name extension for a single host name, which is at most
+    /// 253 bytes as a valid DNS name.
+    pub(crate) fn extension(name: &str) -> ClientExtension {
+        let name = name.trim_end_matches('.').as_bytes();
+        let mut payload = Vec::with_capacity(name.len() + 5);
+        payload.extend_from_slice(&(name.len() as u16 + 3).to_be_bytes());
+        // host_name
+        payload.push(0);
+        payload.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        payload.extend_from_slice(name);
+        ClientExtension::unknown(ExtensionType::ServerName, payload)
+    }
+}
+
+impl Debug for SniMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SniMode::Forward => f.write_str("Forward"),
+            SniMode::Override(name) => f.debug_tuple("Override").field(name).finish(),
+            SniMode::Omit => f.write_str("Omit"),
+            SniMode::Hook(_) => f.write_str("Hook"),
+        }
+    }
+}
+
+/// The host name in rustls's server_name extension.
+pub(crate) fn server_name(extensions: &[ClientExtension]) -> Option<String> {
+    let encoding = extensions
+        .iter()
+        .find(|ext| matches!(ext, ClientExtension::ServerName(_)))?
+        .get_encoding();
+    // type, length, list length, name type and name length
+    let name = encoding.get(9..)?;
+    String::from_utf8(name.to_vec()).ok()
+}
+
+/// Key share with an optional GREASE entry and shares for several groups, in
+/// the order of `groups`.
+///
+/// The share rustls offered is reused for its group and appended if that group
+/// is not listed. Shares for the other groups are generated by the crypto
+/// provider, which should be the config's, and their secrets kept for the
+/// connection, so the server may select any of them. Groups the provider does
+/// not support are left out.
+///
+/// Generating shares needs the `connection-hooks` feature; without it only
+/// the share rustls offered is sent.
+#[derive(Clone, Default)]
+pub struct KeyShares {
+    /// Send a GREASE entry with a one-byte payload first, as Chrome does.
+    pub grease: bool,
+    pub groups: Vec<NamedGroup>,
+    /// Defaults to the process-wide default provider.
+    pub provider: Option<Arc<CryptoProvider>>,
+}
+
+impl KeyShares {
+    fn generate_share(&self, group: NamedGroup) -> Option<Box<dyn ActiveKeyExchange>> {
+        // rustls can only use the secret through the per-connection hooks
+        if !cfg!(feature = "connection-hooks") {
+            return None;
+        }
+        let provider = self.provider.as_ref().or(CryptoProvider::get_default())?;
+        provider
+            .kx_groups
+            .iter()
+            .find(|kx| kx.name() == group)?
+            .start()
+            .ok()
+    }
+}
+
+impl ExtensionGenerator for KeyShares {
+    fn ext_type(&self) -> ExtensionType {
+        ExtensionType::KeyShare
+    }
+
+    fn generate(&self, cx: &mut HelloContext<'_>) -> ClientExtension {
+        let mut offered: Vec<KeyShareEntry> = cx
+            .original_extensions()
+            .iter()
+            .find_map(|ext| match ext {
+                ClientExtension::KeyShare(entries) => Some(entries.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut entries = Vec::with_capacity(self.groups.len() + 2);
+        if self.grease {
+            entries.push(KeyShareEntry::new(
+                NamedGroup::from(cx.grease(GreaseSlot::Group)),
+                vec![0],
+            ));
+        }
+        for group in self.groups.iter() {
+            if let Some(pos) = offered.iter().position(|e| e.group() == *group) {
+                entries.push(offered.remove(pos));
+            } else if let Some(kx) = self.generate_share(*group) {
+                entries.push(KeyShareEntry::new(*group, kx.pub_key()));
+                cx.key_exchanges.push(kx);
+            }
+        }
+        entries.extend(offered);
+        ClientExtension::KeyShare(entries)
+    }
+}
+
+/// How large a GREASE ECH payload is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchPayloadLen {
+    /// `base` plus the server name length, rounded up to a multiple of 32 as
+    /// an encrypted inner hello would be, plus the AEAD tag.
+    FromSni { base: usize },
+    /// A random multiple of 32 in a range, plus the AEAD tag, see
+    /// [`EchPayloadLen::random`].
+    Random(PayloadRange),
+}
+
+/// Bounds of [`EchPayloadLen::Random`], with a multiple of 32 in between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadRange {
+    min: usize,
+    max: usize,
+}
+
+impl EchPayloadLen {
+    /// A random multiple of 32 in `min..=max`, which must contain one.
+    pub fn random(min: usize, max: usize) -> Result<Self, Error> {
+        if min.div_ceil(32) > max / 32 {
+            return Err(Error::EmptyRange { min, max });
+        }
+        Ok(EchPayloadLen::Random(PayloadRange { min, max }))
+    }
+}
+
+/// GREASE ECH with a new random payload on every connection. The default
+/// matches Chrome: X25519, HKDF-SHA256, AES-128-GCM and a random config id.
+#[derive(Debug, Clone, Copy)]
+pub struct GreaseEch {
+    pub kem: HpkeKem,
+    pub kdf: HpkeKdf,
+    pub aead: HpkeAead,
+    /// A random one for each connection if unset.
+    pub config_id: Option<u8>,
+    pub payload_len: EchPayloadLen,
+}
+
+impl Default for GreaseEch {
+    fn default() -> Self {
+        Self {
+            kem: HpkeKem::DHKEM_X25519_HKDF_SHA256,
+            kdf: HpkeKdf::HKDF_SHA256,
+            aead: HpkeAead::AES_128_GCM,
+            config_id: None,
+            // 144 bytes for names of up to 16 characters
+            payload_len: EchPayloadLen::FromSni { base: 112 },
+        }
+    }
+}
+
+impl GreaseEch {
+    /// Build the extension for a connection to a server name of
+    /// `server_name_len` bytes, if any.
+    pub fn payload(&self, server_name_len: Option<usize>, rng: &mut impl Rng) -> ClientExtension {
+        let enc_len = match self.kem {
+            HpkeKem::DHKEM_P256_HKDF_SHA256 => 65,
+            HpkeKem::DHKEM_P384_HKDF_SHA384 => 97,
+            HpkeKem::DHKEM_P521_HKDF_SHA512 => 133,
+            HpkeKem::DHKEM_X448_HKDF_SHA512 => 56,
+            _ => 32,
+        };
+        let tag_len = match self.aead {
+            HpkeAead::EXPORT_ONLY => 0,
+            _ => 16,
+        };
+        let inner_len = match self.payload_len {
+            EchPayloadLen::FromSni { base } => (base + server_name_len.unwrap_or(0)).div_ceil(32),
+            EchPayloadLen::Random(PayloadRange { min, max }) => {
+                rng.gen_range(min.div_ceil(32)..=max / 32)
+            }
+        } * 32;
+        let payload_len = inner_len + tag_len;
+
+        let mut payload = Vec::with_capacity(10 + enc_len + payload_len);
+        // Outer Client Hello
+        payload.push(0);
+        payload.extend_from_slice(&u16::from(self.kdf).to_be_bytes());
+        payload.extend_from_slice(&u16::from(self.aead).to_be_bytes());
+        // Config Id
+        payload.push(self.config_id.unwrap_or_else(|| rng.gen()));
+        // Enc Length+Payload
+        payload.extend_from_slice(&(enc_len as u16).to_be_bytes());
+        for _ in 0..enc_len {
+            payload.push(rng.gen());
+        }
+        // Payload Length+Payload
+        payload.extend_from_slice(&(payload_len as u16).to_be_bytes());
+        for _ in 0..payload_len {
+            payload.push(rng.gen());
+        }
+        ClientExtension::unknown(ExtensionType::EncryptedClientHello, payload)
+    }
+}
+
+impl ExtensionGenerator for GreaseEch {
+    fn ext_type(&self) -> ExtensionType {
+        ExtensionType::EncryptedClientHello
+    }
+
+    fn generate(&self, cx: &mut HelloContext<'_>) -> ClientExtension {
+        let server_name_len = cx.server_name_len();
+        self.payload(server_name_len, cx.rng())
+    }
+}
+
+/// A single GREASE ECH extension for `server_name`, with Chrome's parameters.
+/// Use [`GreaseEch`] in a profile so that the payload differs between
+/// connections.
+pub fn grease_ech(server_name: Option<&str>) -> ClientExtension {
+    GreaseEch::default().payload(server_name.map(str::len), &mut rand::thread_rng())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builder::JAOverrideBuilder;
+    use crate::parse::RawClientHello;
+    use crate::test_util::*;
+    use rand::SeedableRng;
+    use rustls::ProtocolVersion;
+
+    #[test]
+    fn test_grease_ech_per_connection() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        let overrider = JAOverrideBuilder::default()
+            .with_ja3_full("771,4865,65037,,")
+            .unwrap();
+        let first = overrider.override_extensions(vec![]);
+        let second = overrider.override_extensions(vec![]);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].get_ext_type(), ExtensionType::EncryptedClientHello);
+        assert_ne!(format!("{:?}", first), format!("{:?}", second));
+        #[cfg(feature = "ja3")]
+        assert_eq!(overrider.ja3_full(), "771,4865,65037,,");
+    }
+
+    #[test]
+    fn test_key_shares() {
+        use crate::extensions::HelloContext;
+        use rustls::internal::msgs::handshake::KeyShareEntry;
+        let offered = [ClientExtension::KeyShare(vec![KeyShareEntry::new(
+            NamedGroup::X25519,
+            vec![9; 32],
+        )])];
+        let mut cx = HelloContext::new(&offered, None, ChaCha20Rng::seed_from_u64(0));
+        let ExtensionChunk::Generated(g) =
+            ExtensionChunk::key_share(true, vec![NamedGroup::X25519])
+        else {
+            unreachable!()
+        };
+        assert_eq!(g.ext_type(), ExtensionType::KeyShare);
+        let ClientExtension::KeyShare(entries) = g.generate(&mut cx) else {
+            panic!("not a key share")
+        };
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            u16::from(entries[0].group()),
+            cx.grease(crate::extensions::GreaseSlot::Group)
+        );
+        assert_eq!(entries[1].group(), NamedGroup::X25519);
+    }
+
+    #[test]
+    fn test_grease_values() {
+        use rustls::client::client_hello::ClientHelloOverride;
+        use rustls::internal::msgs::codec::Codec;
+        let overrider = JAOverrideBuilder::default()
+            .with_grease(true)
+            .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+            .with_ja3_full("771,4865,2570-0-10-2570-43-2570,29,0")
+            .unwrap();
+        for _ in 0..64 {
+            let sent = overrider.override_extensions(vec![]);
+            let grease: Vec<u16> = sent
+                .iter()
+                .map(|e| u16::from(e.get_ext_type()))
+                .filter(|t| TLS_GREASE_VALUES_INT.contains(t))
+                .collect();
+            assert_eq!(grease.len(), 3);
+            assert!(grease[0] != grease[1] && grease[1] != grease[2] && grease[0] != grease[2]);
+            let payload_lens: Vec<usize> = sent
+                .iter()
+                .filter(|e| TLS_GREASE_VALUES_INT.contains(&u16::from(e.get_ext_type())))
+                .map(|e| e.get_encoding().len() - 4)
+                .collect();
+            assert_eq!(payload_lens, [0, 1, 0]);
+        }
+
+        // the captured Chrome hello round-trips
+        let record = decode_hex(CHROME_HELLO_RECORD);
+        let captured: Vec<Vec<u8>> = RawClientHello::parse(&record)
+            .unwrap()
+            .extensions
+            .into_iter()
+            .filter(|e| TLS_GREASE_VALUES_INT.contains(&e.typ))
+            .map(|e| e.payload)
+            .collect();
+        let sent: Vec<Vec<u8>> = JAOverrideBuilder::default()
+            .from_client_hello_bytes(&record)
+            .unwrap()
+            .override_extensions(vec![])
+            .iter()
+            .filter(|e| TLS_GREASE_VALUES_INT.contains(&u16::from(e.get_ext_type())))
+            .map(|e| e.get_encoding()[4..].to_vec())
+            .collect();
+        assert_eq!(captured, sent);
+    }
+
+    #[test]
+    fn test_grease_ech_sizing() {
+        use crate::extensions::{grease_ech, EchPayloadLen};
+        use rustls::internal::msgs::codec::Codec;
+        let len = |ext: ClientExtension| ext.get_encoding().len() - 4;
+        // kdf, aead, config id, 32-byte enc and a 144-byte payload
+        assert_eq!(len(grease_ech(Some("example.com"))), 1 + 4 + 1 + 34 + 146);
+        assert_eq!(len(grease_ech(None)), 1 + 4 + 1 + 34 + 146);
+        assert_eq!(len(grease_ech(Some(&"a".repeat(17)))), 1 + 4 + 1 + 34 + 178);
+
+        let ech = GreaseEch {
+            kem: HpkeKem::DHKEM_P256_HKDF_SHA256,
+            aead: HpkeAead::CHACHA20_POLY_1305,
+            config_id: Some(7),
+            payload_len: EchPayloadLen::random(128, 224).unwrap(),
+            ..Default::default()
+        };
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        for _ in 0..16 {
+            let payload = ech.payload(Some(11), &mut rng).get_encoding();
+            assert_eq!(&payload[5..10], &[0, 1, 0, 3, 7]);
+            assert_eq!(&payload[10..12], &65u16.to_be_bytes());
+            let inner = u16::from_be_bytes([payload[77], payload[78]]) as usize - 16;
+            assert!(inner.is_multiple_of(32) && (128..=224).contains(&inner));
+        }
+        assert_eq!(
+            EchPayloadLen::random(224, 128),
+            Err(Error::EmptyRange { min: 224, max: 128 })
+        );
+        assert_eq!(
+            EchPayloadLen::random(33, 63),
+            Err(Error::EmptyRange { min: 33, max: 63 })
+        );
+        assert_eq!(
+            EchPayloadLen::random(1, 31),
+            Err(Error::EmptyRange { min: 1, max: 31 })
+        );
+        let ech = GreaseEch {
+            payload_len: EchPayloadLen::random(33, 95).unwrap(),
+            ..Default::default()
+        };
+        for _ in 0..16 {
+            let payload = ech.payload(None, &mut rng).get_encoding();
+            assert_eq!(payload.len(), 4 + 1 + 4 + 1 + 34 + 2 + 64 + 16);
+        }
+
+        // sized from the name the override sends, not rustls's
+        use rustls::client::client_hello::ClientHelloOverride;
+        let ech_len = |sni: SniMode| {
+            let overrider = JAOverrideBuilder::default()
+                .push_extension(ExtensionChunk::Sni(sni))
+                .push_extension(ExtensionChunk::generator(GreaseEch::default()))
+                .build()
+                .unwrap();
+            len(overrider.override_extensions(vec![]).pop().unwrap())
+        };
+        assert_eq!(ech_len(SniMode::Omit), 1 + 4 + 1 + 34 + 146);
+        assert_eq!(
+            ech_len(SniMode::override_name(&"a".repeat(40)).unwrap()),
+            1 + 4 + 1 + 34 + 178
+        );
+    }
+
+    #[test]
+    fn test_pq_key_share() {
+        use crate::builder::X25519_MLKEM768;
+        use rustls::internal::msgs::handshake::KeyShareEntry;
+        // groups of the key share sent when rustls offered an X25519 share
+        let key_share = |builder: &mut JAOverrideBuilder, groups: &str| {
+            let overrider = builder
+                .with_ja3_full(&format!("771,4865,10-51,{groups},0"))
+                .unwrap();
+            let offered = vec![ClientExtension::KeyShare(vec![KeyShareEntry::new(
+                NamedGroup::X25519,
+                vec![7; 32],
+            )])];
+            overrider
+                .connection()
+                .extensions(offered, false)
+                .into_iter()
+                .find_map(|ext| match ext {
+                    ClientExtension::KeyShare(entries) => {
+                        Some(entries.iter().map(|e| e.group()).collect::<Vec<_>>())
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let is_grease = |group: &NamedGroup| TLS_GREASE_VALUES_INT.contains(&u16::from(*group));
+
+        // the default provider has no hybrid group, which is then only listed
+        // in supported_groups; the GREASE entry only follows the flag
+        let groups = key_share(&mut JAOverrideBuilder::default(), "4588-29-23-24");
+        assert_eq!(groups, [NamedGroup::X25519]);
+        let groups = key_share(
+            JAOverrideBuilder::default().with_grease(true),
+            "4588-29-23-24",
+        );
+        assert!(is_grease(&groups[0]));
+        assert_eq!(groups[1..], [NamedGroup::X25519]);
+
+        // the hybrid share is paired with the most preferred classical group
+        // the provider supports
+        let groups = key_share(
+            JAOverrideBuilder::default()
+                .with_grease(true)
+                .with_client_config(&hybrid_client_config()),
+            "4588-23-29",
+        );
+        assert!(is_grease(&groups[0]));
+        if cfg!(feature = "connection-hooks") {
+            assert_eq!(
+                groups[1..],
+                [X25519_MLKEM768, NamedGroup::secp256r1, NamedGroup::X25519]
+            );
+        } else {
+            // without the hooks only the share rustls offered is sent
+            assert_eq!(groups[1..], [NamedGroup::X25519]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "connection-hooks")]
+    fn test_pq_key_share_generated() {
+        use crate::builder::X25519_MLKEM768;
+        use crate::extensions::{HelloContext, KeyShares};
+        use crate::parse::Reader;
+        use rustls::internal::msgs::codec::Codec;
+        use rustls::internal::msgs::handshake::KeyShareEntry;
+        use rustls::{ClientConfig, SignatureScheme};
+        // rustls holds the secret of the hybrid share, the classical one is
+        // produced by the provider
+        let provider = ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth()
+            .crypto_provider()
+            .clone();
+        let offered = [ClientExtension::KeyShare(vec![KeyShareEntry::new(
+            X25519_MLKEM768,
+            vec![7; 1216],
+        )])];
+        let mut cx = HelloContext::new(&offered, None, ChaCha20Rng::seed_from_u64(0));
+        let shares = KeyShares {
+            grease: true,
+            groups: vec![X25519_MLKEM768, NamedGroup::X25519],
+            provider: Some(provider),
+        };
+        let ClientExtension::KeyShare(entries) = shares.generate(&mut cx) else {
+            panic!("not a key share")
+        };
+        let groups: Vec<_> = entries.iter().map(|e| e.group()).collect();
+        assert_eq!(groups[1..], [X25519_MLKEM768, NamedGroup::X25519]);
+        assert_eq!(entries[1].get_encoding().len(), 2 + 2 + 1216);
+        assert_eq!(entries[2].get_encoding().len(), 2 + 2 + 32);
+
+        // a server that only accepts the hybrid group selects the added share
+        let overrider = JAOverrideBuilder::default()
+            .with_grease(true)
+            .with_tls_versions(vec![ProtocolVersion::TLSv1_3])
+            .with_signature_algorithms(vec![SignatureScheme::ECDSA_NISTP256_SHA256])
+            .with_client_config(&hybrid_client_config())
+            .with_ja3_full("771,4865,0-10-13-43-51,4588-29-23,0")
+            .unwrap();
+        let (hellos, _) = handshake(overrider, &[X25519_MLKEM768]);
+        assert_eq!(hellos.len(), 1);
+        let key_share = hellos[0]
+            .extensions
+            .iter()
+            .find(|e| e.typ == u16::from(ExtensionType::KeyShare))
+            .unwrap();
+        let mut entries = Reader::new(&key_share.payload[2..]);
+        let mut groups = Vec::new();
+        while !entries.is_empty() {
+            groups.push(NamedGroup::from(entries.u16().unwrap()));
+            entries.vec_u16().unwrap();
+        }
+        assert_eq!(groups[1..], [X25519_MLKEM768, NamedGroup::X25519]);
+    }
 }
@@ -1,59 +1,101 @@
 use crate::builder::ExtensionChunk;
+use crate::error::Error;
+use crate::extensions::SniMode;
+use crate::parse::{RawClientHello, RawServerHello, Reader};
 use crate::JAOverride;
+#[cfg(any(feature = "ja3", feature = "ja4"))]
+use crate::TLS_GREASE_VALUES_INT;
 use rustls::internal::msgs::enums::ExtensionType;
 use rustls::internal::msgs::handshake::ClientExtension;
-use rustls::ProtocolVersion;
+#[cfg(feature = "ja4")]
 use sha2::Digest;
+#[cfg(feature = "ja4")]
 use std::fmt::Write;
 
-const TLS_GREASE_VALUES_INT: [u16; 16] = [
-    0x0a0a, 0x1a1a, 0x2a2a, 0x3a3a, 0x4a4a, 0x5a5a, 0x6a6a, 0x7a7a, 0x8a8a, 0x9a9a, 0xaaaa, 0xbaba,
-    0xcaca, 0xdada, 0xeaea, 0xfafa,
-];
+/// The parts of a ClientHello that JA3 and JA4 are computed from, in wire order.
+/// GREASE values are kept here and filtered out when fingerprinting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHelloInfo {
+    pub legacy_version: u16,
+    pub cipher_suites: Vec<u16>,
+    pub extensions: Vec<u16>,
+    pub named_groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+    pub supported_versions: Vec<u16>,
+    pub alpn: Vec<Vec<u8>>,
+    pub transport: Transport,
+}
+
+impl ClientHelloInfo {
+    /// Accepts either a bare handshake message or TLS records, like
+    /// [`RawClientHello::parse`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::from_raw(&RawClientHello::parse(bytes)?)
+    }
+
+    pub fn from_raw(hello: &RawClientHello) -> Result<Self, Error> {
+        let mut info = ClientHelloInfo {
+            legacy_version: hello.legacy_version,
+            cipher_suites: hello.cipher_suites.clone(),
+            transport: if (0xfe00..=0xfeff).contains(&hello.legacy_version) {
+                Transport::Dtls
+            } else {
+                Transport::Tcp
+            },
+            ..Default::default()
+        };
+        for ext in hello.extensions.iter() {
+            info.extensions.push(ext.typ);
+            let mut r = Reader::new(&ext.payload);
+            match ExtensionType::from(ext.typ) {
+                ExtensionType::EllipticCurves => {
+                    info.named_groups = Reader::new(r.vec_u16()?).u16_list()?;
+                }
+                ExtensionType::ECPointFormats => {
+                    info.ec_point_formats = r.vec_u8()?.to_vec();
+                }
+                ExtensionType::SignatureAlgorithms => {
+                    info.signature_algorithms = Reader::new(r.vec_u16()?).u16_list()?;
+                }
+                ExtensionType::SupportedVersions => {
+                    info.supported_versions = Reader::new(r.vec_u8()?).u16_list()?;
+                }
+                ExtensionType::ALProtocolNegotiation => {
+                    let mut sub = Reader::new(r.vec_u16()?);
+                    while !sub.is_empty() {
+                        info.alpn.push(sub.vec_u8()?.to_vec());
+                    }
+                }
+                // QUIC is the only user of transport parameters
+                ExtensionType::TransportParameters | ExtensionType::TransportParametersDraft => {
+                    info.transport = Transport::Quic;
+                }
+                _ => {}
+            }
+        }
+        Ok(info)
+    }
+
+    #[cfg(feature = "ja4")]
+    fn has_sni(&self) -> bool {
+        self.extensions
+            .contains(&u16::from(ExtensionType::ServerName))
+    }
 
-impl JAOverride {
     #[cfg(feature = "ja3")]
     pub fn ja3_full(&self) -> String {
         format!(
             "{},{},{},{},{}",
-            771,
-            self.cipher_suites
+            self.legacy_version,
+            join_without_grease(&self.cipher_suites),
+            join_without_grease(&self.extensions),
+            join_without_grease(&self.named_groups),
+            self.ec_point_formats
                 .iter()
-                .map(|c| u16::from(*c).to_string())
-                .collect::<Vec<_>>()
-                .join("-"),
-            extension_to_vec(&self.extensions)
-                .into_iter()
-                .map(|v| v.to_string())
-                .collect::<Vec<_>>()
+                .map(|f| f.to_string())
+                .collect::<Vec<String>>()
                 .join("-"),
-            self.extensions
-                .iter()
-                .filter_map(|e| match e {
-                    ExtensionChunk::Extension(ClientExtension::NamedGroups(g)) => Some(g),
-                    ExtensionChunk::GreasedNameGroups(g) => Some(g),
-                    _ => None,
-                })
-                .next()
-                .map(|e| e
-                    .iter()
-                    .map(|s| u16::from(*s).to_string())
-                    .collect::<Vec<String>>()
-                    .join("-"))
-                .unwrap_or_default(),
-            self.extensions
-                .iter()
-                .filter_map(|e| match e {
-                    ExtensionChunk::Extension(ClientExtension::EcPointFormats(f)) => Some(f),
-                    _ => None,
-                })
-                .next()
-                .map(|e| e
-                    .iter()
-                    .map(|f| u8::from(*f).to_string())
-                    .collect::<Vec<String>>()
-                    .join("-"))
-                .unwrap_or_default(),
         )
     }
 
@@ -64,128 +106,409 @@ impl JAOverride {
 
     #[cfg(feature = "ja4")]
     pub fn ja4_hash(&self) -> String {
-        let tls_versions = self
-            .extensions
-            .iter()
-            .filter_map(|e| match e {
-                ExtensionChunk::GreasedTLSVersion(v) => Some(v),
-                ExtensionChunk::Extension(ClientExtension::SupportedVersions(v)) => Some(v),
-                _ => None,
-            })
-            .next()
-            .map(|v| {
-                v.first()
-                    .map(|v| match *v {
-                        ProtocolVersion::TLSv1_3 => "13",
-                        _ => "12",
-                    })
-                    .unwrap_or("12")
-            })
-            .unwrap_or("12");
-        let has_domain = self.extensions.iter().any(|e| {
-            matches!(e, ExtensionChunk::Sni)
-                || matches!(e, ExtensionChunk::Extension(ClientExtension::ServerName(_)))
-        });
-        let mut ciphers = self
-            .cipher_suites
-            .iter()
-            .filter_map(|c| {
-                if TLS_GREASE_VALUES_INT.contains(&u16::from(*c)) {
-                    None
-                } else {
-                    Some(format!("{:04x}", u16::from(*c)))
-                }
-            })
-            .collect::<Vec<String>>();
-        // ignore SNI and ALPN
-        let mut extensions = extension_to_vec(&self.extensions)
-            .into_iter()
-            .filter_map(|s| {
-                if s != 0 && s != 0x10 {
-                    Some(format!("{:04x}", s))
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-        let signature = self
-            .extensions
+        self.ja4_hashed(false)
+    }
+
+    /// JA4 with sorted, unhashed cipher and extension lists.
+    #[cfg(feature = "ja4")]
+    pub fn ja4_r(&self) -> String {
+        self.ja4_raw(false)
+    }
+
+    /// JA4 with cipher and extension lists hashed in their original order.
+    #[cfg(feature = "ja4")]
+    pub fn ja4_o(&self) -> String {
+        self.ja4_hashed(true)
+    }
+
+    /// JA4 with unhashed cipher and extension lists in their original order.
+    #[cfg(feature = "ja4")]
+    pub fn ja4_ro(&self) -> String {
+        self.ja4_raw(true)
+    }
+
+    #[cfg(feature = "ja4")]
+    fn ja4_hashed(&self, original: bool) -> String {
+        format!(
+            "{}_{}_{}",
+            self.ja4_a(),
+            ja4_truncated_hash(&self.ja4_ciphers(original)),
+            ja4_truncated_hash(&self.ja4_extensions(original))
+        )
+    }
+
+    #[cfg(feature = "ja4")]
+    fn ja4_raw(&self, original: bool) -> String {
+        format!(
+            "{}_{}_{}",
+            self.ja4_a(),
+            self.ja4_ciphers(original),
+            self.ja4_extensions(original)
+        )
+    }
+
+    /// `ja4_a`: protocol, TLS version, SNI, cipher and extension counts, ALPN.
+    #[cfg(feature = "ja4")]
+    fn ja4_a(&self) -> String {
+        let protocol = match self.transport {
+            Transport::Tcp => 't',
+            Transport::Quic => 'q',
+            Transport::Dtls => 'd',
+        };
+        // the highest supported version, falling back to the legacy field
+        let version = self
+            .supported_versions
             .iter()
-            .find_map(|e| match e {
-                ExtensionChunk::Extension(ClientExtension::SignatureAlgorithms(s)) => Some(
-                    s.iter()
-                        .map(|s| format!("{:04x}", u16::from(*s)))
-                        .collect::<Vec<String>>(),
-                ),
-                _ => None,
-            })
-            .unwrap_or_default();
-        // sort for ja4
-        ciphers.sort_unstable();
-        extensions.sort_unstable();
-        let part_b = {
-            let bin = sha2::Sha256::new_with_prefix(ciphers.join(",")).finalize();
-            bin[..6].iter().fold(String::new(), |mut output, b| {
-                let _ = write!(&mut output, "{:02x}", b);
-                output
-            })
+            .filter(|v| !TLS_GREASE_VALUES_INT.contains(v))
+            .copied()
+            .max_by_key(|v| ja4_version_rank(*v))
+            .unwrap_or(self.legacy_version);
+        let version = match version {
+            0x0304 => "13",
+            0x0303 => "12",
+            0x0302 => "11",
+            0x0301 => "10",
+            0x0300 => "s3",
+            0x0002 => "s2",
+            0xfeff => "d1",
+            0xfefd => "d2",
+            0xfefc => "d3",
+            _ => "00",
         };
-        let part_c = {
-            let mut hasher = sha2::Sha256::new_with_prefix(extensions.join(","));
-            hasher.update(b"_");
-            hasher.update(signature.join(","));
-            let bin = hasher.finalize();
-            bin[..6].iter().fold(String::new(), |mut output, b| {
-                let _ = write!(&mut output, "{:02x}", b);
-                output
-            })
+        let ciphers = count_without_grease(&self.cipher_suites);
+        let extensions = count_without_grease(&self.extensions);
+        let alpn = match self
+            .alpn
+            .first()
+            .and_then(|a| Some((*a.first()?, *a.last()?)))
+        {
+            Some((first, last))
+                if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() =>
+            {
+                format!("{}{}", first as char, last as char)
+            }
+            Some((first, last)) => {
+                let first = format!("{:02x}", first);
+                let last = format!("{:02x}", last);
+                format!("{}{}", &first[..1], &last[1..])
+            }
+            None => "00".to_string(),
         };
-        let alpn = self
-            .extensions
-            .iter()
-            .filter_map(|e| match e {
-                ExtensionChunk::Extension(ClientExtension::Protocols(p)) => Some(p),
-                _ => None,
-            })
-            .next()
-            .map(|p| {
-                p.first()
-                    .map(|n| {
-                        if n.as_ref() == b"h2" {
-                            "h2"
-                        } else if n.as_ref() == b"http/1.1" {
-                            "h1"
-                        } else {
-                            "00"
-                        }
-                    })
-                    .unwrap_or("00")
-            })
-            .unwrap_or("00");
         format!(
-            "t{}{}{}{}{}_{}_{}",
-            tls_versions,
-            if has_domain { "d" } else { "i" },
-            ciphers.len(),
-            extensions.len(),
-            alpn,
-            part_b,
-            part_c
+            "{}{}{}{:02}{:02}{}",
+            protocol,
+            version,
+            if self.has_sni() { "d" } else { "i" },
+            ciphers.min(99),
+            extensions.min(99),
+            alpn
         )
     }
+
+    /// Cipher suites without GREASE, sorted unless the original order is asked.
+    #[cfg(feature = "ja4")]
+    fn ja4_ciphers(&self, original: bool) -> String {
+        let mut ciphers = hex_without_grease(&self.cipher_suites);
+        if !original {
+            ciphers.sort_unstable();
+        }
+        ciphers.join(",")
+    }
+
+    /// Extensions without GREASE followed by the signature algorithms in their
+    /// original order. The sorted form also leaves out SNI and ALPN.
+    #[cfg(feature = "ja4")]
+    fn ja4_extensions(&self, original: bool) -> String {
+        let mut extensions = hex_without_grease(&self.extensions);
+        if !original {
+            extensions.retain(|e| e != "0000" && e != "0010");
+            extensions.sort_unstable();
+        }
+        ja4_with_signatures(extensions, hex_without_grease(&self.signature_algorithms))
+    }
+}
+
+/// The parts of a ServerHello that JA3S is computed from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerHelloInfo {
+    pub legacy_version: u16,
+    pub cipher_suite: u16,
+    pub extensions: Vec<u16>,
+}
+
+impl ServerHelloInfo {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(Self::from_raw(&RawServerHello::parse(bytes)?))
+    }
+
+    pub fn from_raw(hello: &RawServerHello) -> Self {
+        ServerHelloInfo {
+            legacy_version: hello.legacy_version,
+            cipher_suite: hello.cipher_suite,
+            extensions: hello.extensions.iter().map(|e| e.typ).collect(),
+        }
+    }
+
+    #[cfg(feature = "ja3")]
+    pub fn ja3s_full(&self) -> String {
+        format!(
+            "{},{},{}",
+            self.legacy_version,
+            self.cipher_suite,
+            join_without_grease(&self.extensions)
+        )
+    }
+
+    #[cfg(feature = "ja3")]
+    pub fn ja3s_hash(&self) -> String {
+        format!("{:x}", md5::compute(self.ja3s_full().as_bytes()))
+    }
 }
 
-fn extension_to_vec(ext: &[ExtensionChunk]) -> Vec<u16> {
+/// Transport protocol the ClientHello was sent over, the first character of JA4.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Quic,
+    Dtls,
+}
+
+#[cfg(feature = "ja4")]
+fn count_without_grease(values: &[u16]) -> usize {
+    values
+        .iter()
+        .filter(|v| !TLS_GREASE_VALUES_INT.contains(v))
+        .count()
+}
+
+#[cfg(feature = "ja4")]
+fn hex_without_grease(values: &[u16]) -> Vec<String> {
+    values
+        .iter()
+        .filter(|v| !TLS_GREASE_VALUES_INT.contains(v))
+        .map(|v| format!("{:04x}", v))
+        .collect()
+}
+
+/// DTLS versions count downwards, and SSL 2 sorts below everything else.
+#[cfg(feature = "ja4")]
+fn ja4_version_rank(version: u16) -> u32 {
+    match version {
+        0xfe00..=0xfeff => 0x0300 + (0xfeff - version) as u32,
+        v => v as u32,
+    }
+}
+
+/// The string ends without an underscore when no signature algorithms are sent.
+#[cfg(feature = "ja4")]
+fn ja4_with_signatures(extensions: Vec<String>, signatures: Vec<String>) -> String {
+    if signatures.is_empty() {
+        extensions.join(",")
+    } else {
+        format!("{}_{}", extensions.join(","), signatures.join(","))
+    }
+}
+
+/// First 12 hex characters of SHA-256, or all zeros for an empty list.
+#[cfg(feature = "ja4")]
+fn ja4_truncated_hash(input: &str) -> String {
+    if input.is_empty() {
+        return "000000000000".to_string();
+    }
+    let bin = sha2::Sha256::new_with_prefix(input).finalize();
+    bin[..6].iter().fold(String::new(), |mut output, b| {
+        let _ = write!(&mut output, "{:02x}", b);
+        output
+    })
+}
+
+#[cfg(feature = "ja3")]
+fn join_without_grease(values: &[u16]) -> String {
+    values
+        .iter()
+        .filter(|v| !TLS_GREASE_VALUES_INT.contains(v))
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+impl JAOverride {
+    /// Fingerprint inputs of the ClientHello this override produces. Chunks
+    /// filled in per connection only contribute their extension type, and
+    /// server_name is counted unless it is always omitted.
+    pub fn client_hello_info(&self) -> ClientHelloInfo {
+        self.hello_info(|mode| !matches!(mode, SniMode::Omit))
+    }
+
+    /// Like [`JAOverride::client_hello_info`], with server_name as sent on a
+    /// connection to `server_name`, the name given to rustls.
+    pub fn client_hello_info_for(&self, server_name: Option<&str>) -> ClientHelloInfo {
+        self.hello_info(|mode| mode.resolve(server_name).is_some())
+    }
+
+    fn hello_info(&self, sends_sni: impl Fn(&SniMode) -> bool) -> ClientHelloInfo {
+        let mut info = ClientHelloInfo {
+            legacy_version: u16::from(self.legacy_version),
+            cipher_suites: self.cipher_suites.iter().map(|c| u16::from(*c)).collect(),
+            extensions: extension_to_vec(&self.extensions, sends_sni),
+            ..Default::default()
+        };
+        for ext in self.extensions.iter() {
+            match ext {
+                ExtensionChunk::GreasedNameGroups(g)
+                | ExtensionChunk::Extension(ClientExtension::NamedGroups(g)) => {
+                    info.named_groups = g.iter().map(|g| u16::from(*g)).collect();
+                }
+                ExtensionChunk::GreasedTLSVersion(v)
+                | ExtensionChunk::Extension(ClientExtension::SupportedVersions(v)) => {
+                    info.supported_versions = v.iter().map(|v| u16::from(*v)).collect();
+                }
+                ExtensionChunk::Extension(ClientExtension::EcPointFormats(f)) => {
+                    info.ec_point_formats = f.iter().map(|f| u8::from(*f)).collect();
+                }
+                ExtensionChunk::Extension(ClientExtension::SignatureAlgorithms(s)) => {
+                    info.signature_algorithms = s.iter().map(|s| u16::from(*s)).collect();
+                }
+                ExtensionChunk::Extension(ClientExtension::Protocols(p)) => {
+                    info.alpn = p.iter().map(|p| p.as_ref().to_vec()).collect();
+                }
+                _ => {}
+            }
+        }
+        info
+    }
+
+    #[cfg(feature = "ja3")]
+    pub fn ja3_full(&self) -> String {
+        self.client_hello_info().ja3_full()
+    }
+
+    #[cfg(feature = "ja3")]
+    pub fn ja3_hash(&self) -> String {
+        self.client_hello_info().ja3_hash()
+    }
+
+    #[cfg(feature = "ja4")]
+    pub fn ja4_hash(&self) -> String {
+        self.client_hello_info().ja4_hash()
+    }
+
+    /// JA4 of a connection to `server_name`, whose `d`/`i` flag follows the
+    /// [`SniMode`] of the profile.
+    #[cfg(feature = "ja4")]
+    pub fn ja4_hash_for(&self, server_name: Option<&str>) -> String {
+        self.client_hello_info_for(server_name).ja4_hash()
+    }
+
+    #[cfg(feature = "ja4")]
+    pub fn ja4_r(&self) -> String {
+        self.client_hello_info().ja4_r()
+    }
+
+    /// Uses the order of the profile, before any extension shuffling.
+    #[cfg(feature = "ja4")]
+    pub fn ja4_o(&self) -> String {
+        self.client_hello_info().ja4_o()
+    }
+
+    /// Uses the order of the profile, before any extension shuffling.
+    #[cfg(feature = "ja4")]
+    pub fn ja4_ro(&self) -> String {
+        self.client_hello_info().ja4_ro()
+    }
+}
+
+fn extension_to_vec(ext: &[ExtensionChunk], sends_sni: impl Fn(&SniMode) -> bool) -> Vec<u16> {
     ext.iter()
         .filter_map(|e| match e {
             ExtensionChunk::Grease => None,
-            ExtensionChunk::Sni => Some(u16::from(ExtensionType::ServerName)),
+            ExtensionChunk::Sni(mode) => {
+                sends_sni(mode).then_some(u16::from(ExtensionType::ServerName))
+            }
             ExtensionChunk::KeyShare => Some(u16::from(ExtensionType::KeyShare)),
             ExtensionChunk::GreasedNameGroups(_) => Some(u16::from(ExtensionType::EllipticCurves)),
             ExtensionChunk::GreasedTLSVersion(_) => {
                 Some(u16::from(ExtensionType::SupportedVersions))
             }
             ExtensionChunk::Extension(e) => Some(u16::from(e.get_ext_type())),
+            ExtensionChunk::Generated(g) => Some(u16::from(g.ext_type())),
+            ExtensionChunk::Padding(_) => Some(u16::from(ExtensionType::Padding)),
+            ExtensionChunk::Passthrough(t) => Some(u16::from(*t)),
+            ExtensionChunk::SessionTicket => Some(u16::from(ExtensionType::SessionTicket)),
         })
         .collect::<Vec<u16>>()
 }
+
+#[cfg(all(test, feature = "ja4"))]
+mod test {
+    use super::*;
+
+    fn parse_hex_list(list: &str) -> Vec<u16> {
+        list.split(',')
+            .map(|v| u16::from_str_radix(v, 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_ja4_foxio_chrome() {
+        // the FoxIO example, extensions in the order its JA4_ro lists them
+        let info = ClientHelloInfo {
+            legacy_version: 0x0303,
+            cipher_suites: parse_hex_list(
+                "1301,1302,1303,c02b,c02f,c02c,c030,cca9,cca8,c013,c014,009c,009d,002f,0035",
+            ),
+            extensions: parse_hex_list(
+                "001b,0000,0033,0010,4469,0017,002d,000d,0005,0023,0012,002b,ff01,000b,000a,0015",
+            ),
+            signature_algorithms: parse_hex_list("0403,0804,0401,0503,0805,0501,0806,0601"),
+            supported_versions: parse_hex_list("2a2a,0304,0303"),
+            alpn: vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            ..Default::default()
+        };
+        assert_eq!(info.ja4_hash(), "t13d1516h2_8daaf6152771_e5627efa2ab1");
+        assert_eq!(
+            info.ja4_r(),
+            "t13d1516h2_002f,0035,009c,009d,1301,1302,1303,c013,c014,c02b,c02c,c02f,c030,cca8,cca9_0005,000a,000b,000d,0012,0015,0017,001b,0023,002b,002d,0033,4469,ff01_0403,0804,0401,0503,0805,0501,0806,0601"
+        );
+        assert_eq!(info.ja4_o(), "t13d1516h2_acb858a92679_18f69afefd3d");
+        assert_eq!(
+            info.ja4_ro(),
+            "t13d1516h2_1301,1302,1303,c02b,c02f,c02c,c030,cca9,cca8,c013,c014,009c,009d,002f,0035_001b,0000,0033,0010,4469,0017,002d,000d,0005,0023,0012,002b,ff01,000b,000a,0015_0403,0804,0401,0503,0805,0501,0806,0601"
+        );
+    }
+
+    #[test]
+    fn test_ja4_quic_without_signatures() {
+        // not from FoxIO: regression values computed by this implementation
+        let info = ClientHelloInfo {
+            legacy_version: 0x0303,
+            cipher_suites: parse_hex_list("1303,1301"),
+            extensions: parse_hex_list("0a0a,0039,0010,0000,002b,000a"),
+            supported_versions: parse_hex_list("0a0a,0304"),
+            alpn: vec![b"h3".to_vec()],
+            transport: Transport::Quic,
+            ..Default::default()
+        };
+        assert_eq!(info.ja4_hash(), "q13d0205h3_88d8eb61a8ee_6e5aa5b2101a");
+        assert_eq!(
+            info.ja4_ro(),
+            "q13d0205h3_1303,1301_0039,0010,0000,002b,000a"
+        );
+    }
+
+    #[test]
+    fn test_ja4_edge_cases() {
+        let mut info = ClientHelloInfo {
+            legacy_version: 0x0303,
+            ..Default::default()
+        };
+        assert_eq!(info.ja4_hash(), "t12i000000_000000000000_000000000000");
+        // highest version wins, whatever the order
+        info.supported_versions = parse_hex_list("0302,0304,0303");
+        // non-alphanumeric ALPN is printed as hex
+        info.alpn = vec![vec![0xab, 0x68, 0xcd]];
+        info.cipher_suites = vec![0x1301; 120];
+        assert_eq!(&info.ja4_hash()[..10], "t13i9900ad");
+    }
+}
@@ -1,27 +1,67 @@
 pub mod builder;
+mod connection;
+pub mod ech;
+pub mod error;
 pub mod extensions;
 mod hash;
+pub mod parse;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+#[cfg(test)]
+mod test_util;
 
-use rand::prelude::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use rustls::internal::msgs::handshake::ClientExtension;
-use rustls::{CipherSuite, NamedGroup, ProtocolVersion};
+use rustls::{CipherSuite, ProtocolVersion};
 use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
 
 use crate::builder::{ExtensionChunk, JAOverrideBuilder};
+use crate::connection::ConnectionOverride;
+pub use hash::{ClientHelloInfo, ServerHelloInfo, Transport};
 pub use rustls as rustls_vendor;
 use rustls::client::client_hello::ClientHelloOverride;
+#[cfg(feature = "connection-hooks")]
+use rustls::client::client_hello::ConnectionHelloOverride;
 use rustls::internal::msgs::enums::ExtensionType;
+use rustls::pki_types::EchConfigListBytes;
+
+pub(crate) const TLS_GREASE_VALUES_INT: [u16; 16] = [
+    0x0a0a, 0x1a1a, 0x2a2a, 0x3a3a, 0x4a4a, 0x5a5a, 0x6a6a, 0x7a7a, 0x8a8a, 0x9a9a, 0xaaaa, 0xbaba,
+    0xcaca, 0xdada, 0xeaea, 0xfafa,
+];
 
 pub struct JAOverride {
-    pub(crate) cipher_suites: Vec<CipherSuite>,
-    pub(crate) extensions: Vec<ExtensionChunk>,
+    /// Only reported in fingerprints, rustls always sends TLS 1.2 here.
+    pub(crate) legacy_version: ProtocolVersion,
+    pub(crate) cipher_suites: Arc<[CipherSuite]>,
+    pub(crate) extensions: Arc<[ExtensionChunk]>,
     pub(crate) shuffle_extension: bool,
+    /// Extensions that keep their position when shuffling, in addition to
+    /// GREASE, padding and pre_shared_key.
+    pub(crate) pinned_extensions: Arc<[ExtensionType]>,
+    /// Real ECH is offered by rustls, see [`JAOverride::ech_mode`].
+    pub(crate) ech_config_list: Option<EchConfigListBytes<'static>>,
+    /// Source of every random choice, so that a seeded override is reproducible.
+    pub(crate) rng: Mutex<ChaCha20Rng>,
 }
 
 impl JAOverride {
     pub fn builder() -> JAOverrideBuilder {
         JAOverrideBuilder::default()
     }
+
+    fn hello_rng(&self) -> ChaCha20Rng {
+        // one draw under the lock, so concurrent handshakes do not contend
+        let seed: u64 = self.rng.lock().unwrap_or_else(|e| e.into_inner()).gen();
+        log::debug!("ClientHello seed {:#018x}", seed);
+        ChaCha20Rng::seed_from_u64(seed)
+    }
+
+    fn connection(&self) -> ConnectionOverride {
+        ConnectionOverride::new(self, self.hello_rng())
+    }
 }
 
 impl Debug for JAOverride {
@@ -32,67 +72,15 @@ impl Debug for JAOverride {
 
 impl ClientHelloOverride for JAOverride {
     fn override_cipher_suites(&self, _cipher_suites: Vec<CipherSuite>) -> Vec<CipherSuite> {
-        self.cipher_suites.clone()
+        self.connection().cipher_suites(false)
     }
 
     fn override_extensions(&self, extensions: Vec<ClientExtension>) -> Vec<ClientExtension> {
-        let mut res = Vec::with_capacity(self.extensions.len());
-        for ext in self.extensions.iter() {
-            match ext {
-                ExtensionChunk::Grease => {
-                    res.push(ClientExtension::grease());
-                }
-                ExtensionChunk::Sni => {
-                    if let Some(sni) = extensions
-                        .iter()
-                        .find(|ext| matches!(ext, ClientExtension::ServerName(_)))
-                    {
-                        res.push(sni.clone());
-                    }
-                }
-                ExtensionChunk::KeyShare => {
-                    if let Some(key_share) = extensions
-                        .iter()
-                        .find(|ext| matches!(ext, ClientExtension::KeyShare(_)))
-                    {
-                        res.push(key_share.clone());
-                    }
-                }
-                ExtensionChunk::Extension(ext) => {
-                    res.push(ext.clone());
-                }
-                ExtensionChunk::GreasedNameGroups(v) => {
-                    let mut groups = Vec::with_capacity(v.len() + 1);
-                    groups.push(NamedGroup::grease());
-                    groups.extend(v.iter().cloned());
-                    res.push(ClientExtension::NamedGroups(groups));
-                }
-                ExtensionChunk::GreasedTLSVersion(v) => {
-                    let mut versions = Vec::with_capacity(v.len() + 1);
-                    versions.push(ProtocolVersion::grease());
-                    versions.extend(v.iter().cloned());
-                    res.push(ClientExtension::SupportedVersions(versions));
-                }
-            }
-        }
-        if self.shuffle_extension {
-            let first = self
-                .extensions
-                .iter()
-                .enumerate()
-                .position(|(_, chk)| !matches!(chk, ExtensionChunk::Grease));
-            let last = self.extensions.iter().enumerate().rposition(|(_, chk)| {
-                !matches!(chk, ExtensionChunk::Grease)
-                    && if let ExtensionChunk::Extension(e) = chk {
-                        e.get_ext_type() != ExtensionType::Padding
-                    } else {
-                        true
-                    }
-            });
-            if let (Some(first), Some(last)) = (first, last) {
-                res[first..=last].shuffle(&mut rand::thread_rng());
-            }
-        }
-        res
+        self.connection().single_hello(extensions)
+    }
+
+    #[cfg(feature = "connection-hooks")]
+    fn start_connection(&self) -> Option<Box<dyn ConnectionHelloOverride>> {
+        Some(Box::new(self.connection()))
     }
 }
@@ -0,0 +1,192 @@
+use crate::error::Error;
+
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 0x01;
+const HANDSHAKE_TYPE_SERVER_HELLO: u8 = 0x02;
+const HANDSHAKE_HEADER_LEN: usize = 4;
+
+/// A ClientHello decoded without interpreting any extension payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawClientHello {
+    pub legacy_version: u16,
+    pub random: [u8; 32],
+    pub session_id: Vec<u8>,
+    pub cipher_suites: Vec<u16>,
+    pub compression_methods: Vec<u8>,
+    pub extensions: Vec<RawExtension>,
+}
+
+/// A ServerHello (or HelloRetryRequest) decoded without interpreting any
+/// extension payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawServerHello {
+    pub legacy_version: u16,
+    pub random: [u8; 32],
+    pub session_id: Vec<u8>,
+    pub cipher_suite: u16,
+    pub compression_method: u8,
+    pub extensions: Vec<RawExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawExtension {
+    pub typ: u16,
+    pub payload: Vec<u8>,
+}
+
+impl RawClientHello {
+    /// Accepts either a bare handshake message or one or more TLS records
+    /// carrying the ClientHello.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes.first() {
+            Some(&CONTENT_TYPE_HANDSHAKE) => Self::parse_handshake(&defragment(bytes)?),
+            Some(&HANDSHAKE_TYPE_CLIENT_HELLO) => Self::parse_handshake(bytes),
+            _ => Err(Error::MalformedHello(
+                "not a handshake record or ClientHello",
+            )),
+        }
+    }
+
+    fn parse_handshake(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader::new(bytes);
+        if r.u8()? != HANDSHAKE_TYPE_CLIENT_HELLO {
+            return Err(Error::MalformedHello("not a ClientHello"));
+        }
+        let len = r.u24()?;
+        let mut r = Reader::new(r.take(len)?);
+        let legacy_version = r.u16()?;
+        let mut random = [0u8; 32];
+        random.copy_from_slice(r.take(32)?);
+        let session_id = r.vec_u8()?.to_vec();
+        let cipher_suites = Reader::new(r.vec_u16()?).u16_list()?;
+        let compression_methods = r.vec_u8()?.to_vec();
+        let extensions = read_extensions(&mut r)?;
+        Ok(Self {
+            legacy_version,
+            random,
+            session_id,
+            cipher_suites,
+            compression_methods,
+            extensions,
+        })
+    }
+}
+
+impl RawServerHello {
+    /// Accepts a bare handshake message only, as a ServerHello is usually
+    /// followed by other handshake messages in the same record.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader::new(bytes);
+        if r.u8()? != HANDSHAKE_TYPE_SERVER_HELLO {
+            return Err(Error::MalformedHello("not a ServerHello"));
+        }
+        let len = r.u24()?;
+        let mut r = Reader::new(r.take(len)?);
+        let legacy_version = r.u16()?;
+        let mut random = [0u8; 32];
+        random.copy_from_slice(r.take(32)?);
+        let session_id = r.vec_u8()?.to_vec();
+        let cipher_suite = r.u16()?;
+        let compression_method = r.u8()?;
+        let extensions = read_extensions(&mut r)?;
+        Ok(Self {
+            legacy_version,
+            random,
+            session_id,
+            cipher_suite,
+            compression_method,
+            extensions,
+        })
+    }
+}
+
+/// Extensions are optional in both hellos, so an exhausted reader is accepted.
+fn read_extensions(r: &mut Reader) -> Result<Vec<RawExtension>, Error> {
+    let mut extensions = Vec::new();
+    if !r.is_empty() {
+        let mut sub = Reader::new(r.vec_u16()?);
+        while !sub.is_empty() {
+            let typ = sub.u16()?;
+            let payload = sub.vec_u16()?.to_vec();
+            extensions.push(RawExtension { typ, payload });
+        }
+    }
+    Ok(extensions)
+}
+
+/// Concatenate the fragments of consecutive handshake records.
+fn defragment(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut r = Reader::new(bytes);
+    let mut out = Vec::with_capacity(bytes.len());
+    while !r.is_empty() {
+        if r.u8()? != CONTENT_TYPE_HANDSHAKE {
+            return Err(Error::MalformedHello("not a handshake record"));
+        }
+        // record version
+        r.u16()?;
+        out.extend_from_slice(r.vec_u16()?);
+        if out.len() >= HANDSHAKE_HEADER_LEN {
+            let len = u32::from_be_bytes([0, out[1], out[2], out[3]]) as usize;
+            if out.len() >= HANDSHAKE_HEADER_LEN + len {
+                break;
+            }
+        }
+    }
+    Ok(out)
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.buf.len() < len {
+            return Err(Error::MalformedHello("truncated message"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u24(&mut self) -> Result<usize, Error> {
+        let b = self.take(3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+
+    pub(crate) fn vec_u8(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u8()? as usize;
+        self.take(len)
+    }
+
+    /// Read the rest of the buffer as a list of u16 values.
+    pub(crate) fn u16_list(&mut self) -> Result<Vec<u16>, Error> {
+        let mut list = Vec::with_capacity(self.buf.len() / 2);
+        while !self.is_empty() {
+            list.push(self.u16()?);
+        }
+        Ok(list)
+    }
+
+    pub(crate) fn vec_u16(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+}
@@ -0,0 +1,661 @@
+//! Offline extraction of TLS hellos from pcap and pcapng captures.
+//!
+//! TCP streams are reassembled only as far as the plaintext handshake goes:
+//! once a stream carries anything other than handshake or ChangeCipherSpec
+//! records, the rest of it is ignored.
+
+use crate::error::Error;
+use crate::parse::{RawClientHello, RawServerHello};
+use crate::{ClientHelloInfo, ServerHelloInfo};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const PCAP_MAGIC_US: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NS: u32 = 0xa1b23c4d;
+const PCAPNG_SHB: u32 = 0x0a0d0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const PCAPNG_IDB: u32 = 0x00000001;
+const PCAPNG_PB: u32 = 0x00000002;
+const PCAPNG_SPB: u32 = 0x00000003;
+const PCAPNG_EPB: u32 = 0x00000006;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_RAW_BSD: u32 = 12;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const CONTENT_TYPE_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 0x01;
+const HANDSHAKE_TYPE_SERVER_HELLO: u8 = 0x02;
+
+/// Stop buffering a stream after this many bytes; no plaintext handshake
+/// flight that carries a hello is anywhere near this long.
+const MAX_STREAM_BUFFER: usize = 1 << 20;
+
+/// All hellos seen on one TCP connection. A connection answered with a
+/// HelloRetryRequest carries two of each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowReport {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub client_hellos: Vec<ClientHelloInfo>,
+    pub server_hellos: Vec<ServerHelloInfo>,
+}
+
+/// Read a pcap or pcapng capture and report the hellos of every TLS flow, in
+/// the order the flows first sent a hello.
+pub fn read_capture(bytes: &[u8]) -> Result<Vec<FlowReport>, Error> {
+    let mut extractor = Extractor::default();
+    let magic = bytes
+        .get(..4)
+        .map(|m| u32::from_le_bytes([m[0], m[1], m[2], m[3]]))
+        .ok_or(Error::MalformedCapture("truncated file header"))?;
+    if magic == PCAPNG_SHB {
+        read_pcapng(bytes, &mut extractor)?;
+    } else {
+        read_pcap(bytes, &mut extractor)?;
+    }
+    Ok(extractor.reports)
+}
+
+/// Byte-order aware cursor for capture file headers.
+struct Cursor<'a> {
+    buf: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.buf.len() < len {
+            return Err(Error::MalformedCapture("truncated block"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.take(2)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.take(4)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        } else {
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        })
+    }
+}
+
+fn read_pcap(bytes: &[u8], extractor: &mut Extractor) -> Result<(), Error> {
+    let magic: [u8; 4] = bytes
+        .get(..4)
+        .and_then(|m| m.try_into().ok())
+        .ok_or(Error::MalformedCapture("truncated file header"))?;
+    let big_endian = if [PCAP_MAGIC_US, PCAP_MAGIC_NS].contains(&u32::from_le_bytes(magic)) {
+        false
+    } else if [PCAP_MAGIC_US, PCAP_MAGIC_NS].contains(&u32::from_be_bytes(magic)) {
+        true
+    } else {
+        return Err(Error::MalformedCapture("unknown magic number"));
+    };
+    let mut c = Cursor {
+        buf: &bytes[4..],
+        big_endian,
+    };
+    // version, thiszone, sigfigs, snaplen
+    c.take(16)?;
+    let link_type = c.u32()? & 0x0fff_ffff;
+    while !c.buf.is_empty() {
+        // timestamp
+        c.take(8)?;
+        let captured = c.u32()? as usize;
+        // original length
+        c.u32()?;
+        extractor.packet(link_type, c.take(captured)?);
+    }
+    Ok(())
+}
+
+fn read_pcapng(bytes: &[u8], extractor: &mut Extractor) -> Result<(), Error> {
+    let mut rest = bytes;
+    let mut big_endian = false;
+    // link type and snap length of every interface in the current section
+    let mut interfaces: Vec<(u32, usize)> = Vec::new();
+    while !rest.is_empty() {
+        let mut c = Cursor {
+            buf: rest,
+            big_endian,
+        };
+        let block_type = c.u32()?;
+        if block_type == PCAPNG_SHB {
+            // the byte-order magic follows the length, whose encoding it defines
+            let magic = rest
+                .get(8..12)
+                .ok_or(Error::MalformedCapture("truncated section header"))?;
+            big_endian = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]])
+                == PCAPNG_BYTE_ORDER_MAGIC;
+            c.big_endian = big_endian;
+            interfaces.clear();
+        }
+        let total_len = c.u32()? as usize;
+        if total_len < 12 || !total_len.is_multiple_of(4) || total_len > rest.len() {
+            return Err(Error::MalformedCapture("invalid block length"));
+        }
+        let mut body = Cursor {
+            buf: &rest[8..total_len - 4],
+            big_endian,
+        };
+        match block_type {
+            PCAPNG_IDB => {
+                let link_type = body.u16()? as u32;
+                body.u16()?;
+                let snap_len = body.u32()? as usize;
+                interfaces.push((link_type, snap_len));
+            }
+            PCAPNG_EPB => {
+                let interface = body.u32()? as usize;
+                // timestamp
+                body.take(8)?;
+                let captured = body.u32()? as usize;
+                body.u32()?;
+                let (link_type, _) = *interfaces
+                    .get(interface)
+                    .ok_or(Error::MalformedCapture("unknown interface"))?;
+                extractor.packet(link_type, body.take(captured)?);
+            }
+            PCAPNG_PB => {
+                let interface = body.u16()? as usize;
+                // drops count and timestamp
+                body.take(10)?;
+                let captured = body.u32()? as usize;
+                body.u32()?;
+                let (link_type, _) = *interfaces
+                    .get(interface)
+                    .ok_or(Error::MalformedCapture("unknown interface"))?;
+                extractor.packet(link_type, body.take(captured)?);
+            }
+            PCAPNG_SPB => {
+                let original = body.u32()? as usize;
+                let (link_type, snap_len) = *interfaces
+                    .first()
+                    .ok_or(Error::MalformedCapture("no interface description"))?;
+                let mut captured = original.min(body.buf.len());
+                if snap_len != 0 {
+                    captured = captured.min(snap_len);
+                }
+                extractor.packet(link_type, body.take(captured)?);
+            }
+            _ => {}
+        }
+        rest = &rest[total_len..];
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Extractor {
+    streams: HashMap<(SocketAddr, SocketAddr), Stream>,
+    flows: HashMap<(SocketAddr, SocketAddr), usize>,
+    reports: Vec<FlowReport>,
+}
+
+impl Extractor {
+    /// Malformed or non-TCP packets are skipped silently, as captures are
+    /// routinely truncated or carry unrelated traffic.
+    fn packet(&mut self, link_type: u32, data: &[u8]) {
+        let Some((src, dst, ip_payload)) = strip_link_layer(link_type, data).and_then(strip_ip)
+        else {
+            return;
+        };
+        let Some(segment) = parse_tcp(ip_payload) else {
+            return;
+        };
+        let src = SocketAddr::new(src, segment.src_port);
+        let dst = SocketAddr::new(dst, segment.dst_port);
+        let stream = self.streams.entry((src, dst)).or_default();
+        if segment.syn {
+            // the client port was reused for a new connection
+            if stream.syn(segment.seq) {
+                self.flows.remove(&(src, dst));
+            }
+            return;
+        }
+        for message in stream.push(&segment) {
+            match message.first() {
+                Some(&HANDSHAKE_TYPE_CLIENT_HELLO) => {
+                    if let Ok(info) =
+                        RawClientHello::parse(&message).and_then(|h| ClientHelloInfo::from_raw(&h))
+                    {
+                        self.flow(src, dst).client_hellos.push(info);
+                    }
+                }
+                Some(&HANDSHAKE_TYPE_SERVER_HELLO) => {
+                    if let Ok(hello) = RawServerHello::parse(&message) {
+                        self.flow(dst, src)
+                            .server_hellos
+                            .push(ServerHelloInfo::from_raw(&hello));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn flow(&mut self, client: SocketAddr, server: SocketAddr) -> &mut FlowReport {
+        let idx = *self.flows.entry((client, server)).or_insert_with(|| {
+            self.reports.push(FlowReport {
+                client,
+                server,
+                client_hellos: Vec::new(),
+                server_hellos: Vec::new(),
+            });
+            self.reports.len() - 1
+        });
+        &mut self.reports[idx]
+    }
+}
+
+/// Returns the network-layer packet, tagged with its IP version.
+fn strip_link_layer(link_type: u32, data: &[u8]) -> Option<(u8, &[u8])> {
+    let ip_version = |data: &[u8]| data.first().map(|b| b >> 4);
+    match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ether_type = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            let mut offset = 14;
+            // 802.1Q and 802.1ad tags
+            while ether_type == 0x8100 || ether_type == 0x88a8 {
+                ether_type = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+                offset += 4;
+            }
+            match ether_type {
+                0x0800 => Some((4, data.get(offset..)?)),
+                0x86dd => Some((6, data.get(offset..)?)),
+                _ => None,
+            }
+        }
+        LINKTYPE_RAW | LINKTYPE_RAW_BSD => Some((ip_version(data)?, data)),
+        LINKTYPE_IPV4 => Some((4, data)),
+        LINKTYPE_IPV6 => Some((6, data)),
+        LINKTYPE_NULL => {
+            let data = data.get(4..)?;
+            Some((ip_version(data)?, data))
+        }
+        LINKTYPE_LINUX_SLL => match u16::from_be_bytes([*data.get(14)?, *data.get(15)?]) {
+            0x0800 => Some((4, data.get(16..)?)),
+            0x86dd => Some((6, data.get(16..)?)),
+            _ => None,
+        },
+        LINKTYPE_LINUX_SLL2 => match u16::from_be_bytes([*data.first()?, *data.get(1)?]) {
+            0x0800 => Some((4, data.get(20..)?)),
+            0x86dd => Some((6, data.get(20..)?)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns source, destination and TCP segment. Fragmented packets are not
+/// reassembled.
+fn strip_ip((version, data): (u8, &[u8])) -> Option<(IpAddr, IpAddr, &[u8])> {
+    match version {
+        4 => {
+            let header_len = ((*data.first()? & 0x0f) as usize) * 4;
+            let total_len = u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as usize;
+            let fragment = u16::from_be_bytes([*data.get(6)?, *data.get(7)?]);
+            // more fragments flag or non-zero offset
+            if fragment & 0x3fff != 0 || *data.get(9)? != 6 {
+                return None;
+            }
+            let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+            // some captures have a zero total length when offloading is enabled
+            let end = if total_len == 0 {
+                data.len()
+            } else {
+                total_len.min(data.len())
+            };
+            Some((
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                data.get(header_len..end)?,
+            ))
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes([*data.get(4)?, *data.get(5)?]) as usize;
+            let mut next_header = *data.get(6)?;
+            let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+            let end = if payload_len == 0 {
+                data.len()
+            } else {
+                (40 + payload_len).min(data.len())
+            };
+            let mut offset = 40;
+            // hop-by-hop, routing and destination options
+            while [0, 43, 60].contains(&next_header) {
+                next_header = *data.get(offset)?;
+                offset += (*data.get(offset + 1)? as usize + 1) * 8;
+            }
+            if next_header != 6 {
+                return None;
+            }
+            Some((
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                data.get(offset..end)?,
+            ))
+        }
+        _ => None,
+    }
+}
+
+struct Segment<'a> {
+    src_port: u16,
+    dst_port: u16,
+    seq: u32,
+    syn: bool,
+    payload: &'a [u8],
+}
+
+fn parse_tcp(data: &[u8]) -> Option<Segment<'_>> {
+    let header_len = ((*data.get(12)? >> 4) as usize) * 4;
+    Some(Segment {
+        src_port: u16::from_be_bytes([*data.first()?, *data.get(1)?]),
+        dst_port: u16::from_be_bytes([*data.get(2)?, *data.get(3)?]),
+        seq: u32::from_be_bytes(data.get(4..8)?.try_into().ok()?),
+        syn: *data.get(13)? & 0x02 != 0,
+        payload: data.get(header_len..)?,
+    })
+}
+
+/// One direction of a TCP connection.
+#[derive(Default)]
+struct Stream {
+    /// Sequence number of the SYN, if one was seen.
+    isn: Option<u32>,
+    /// Sequence number of the first payload byte.
+    base: Option<u32>,
+    /// Segments that arrived ahead of a gap, keyed by stream offset.
+    pending: BTreeMap<u32, Vec<u8>>,
+    /// Contiguous bytes from the start of the stream that are not yet parsed
+    /// as records.
+    buffer: Vec<u8>,
+    /// Stream offset of the end of `buffer`.
+    received: u32,
+    /// Handshake bytes not yet forming a complete message.
+    handshake: Vec<u8>,
+    done: bool,
+}
+
+impl Stream {
+    /// Feed a SYN. One with another sequence number than the last starts the
+    /// stream over and returns `true`.
+    fn syn(&mut self, seq: u32) -> bool {
+        let restarted = self.isn.is_some_and(|isn| isn != seq);
+        if restarted {
+            *self = Stream::default();
+        }
+        self.isn = Some(seq);
+        self.base.get_or_insert(seq.wrapping_add(1));
+        restarted
+    }
+
+    /// Feed a segment without SYN and return every handshake message it
+    /// completes.
+    fn push(&mut self, segment: &Segment<'_>) -> Vec<Vec<u8>> {
+        if self.done {
+            return Vec::new();
+        }
+        if segment.payload.is_empty() {
+            return Vec::new();
+        }
+        let base = *self.base.get_or_insert(segment.seq);
+        let offset = segment.seq.wrapping_sub(base);
+        // retransmissions from before a late SYN wrap around to huge offsets
+        if offset as usize > MAX_STREAM_BUFFER {
+            return Vec::new();
+        }
+        self.pending
+            .entry(offset)
+            .and_modify(|p| {
+                if p.len() < segment.payload.len() {
+                    *p = segment.payload.to_vec();
+                }
+            })
+            .or_insert_with(|| segment.payload.to_vec());
+        while let Some(entry) = self.pending.first_entry() {
+            let offset = *entry.key();
+            if offset > self.received {
+                break;
+            }
+            let data = entry.remove();
+            let end = offset.wrapping_add(data.len() as u32);
+            if end > self.received {
+                let skip = (self.received - offset) as usize;
+                self.buffer.extend_from_slice(&data[skip..]);
+                self.received = end;
+            }
+        }
+        if self.received as usize > MAX_STREAM_BUFFER {
+            self.finish();
+            return Vec::new();
+        }
+        self.drain_records()
+    }
+
+    fn drain_records(&mut self) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        let mut consumed = 0;
+        while let Some(header) = self.buffer.get(consumed..consumed + 5) {
+            let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+            let Some(fragment) = self.buffer.get(consumed + 5..consumed + 5 + len) else {
+                break;
+            };
+            match header[0] {
+                CONTENT_TYPE_HANDSHAKE => self.handshake.extend_from_slice(fragment),
+                CONTENT_TYPE_CHANGE_CIPHER_SPEC => {}
+                _ => {
+                    self.finish();
+                    return messages;
+                }
+            }
+            consumed += 5 + len;
+            while self.handshake.len() >= 4 {
+                let len = u32::from_be_bytes([
+                    0,
+                    self.handshake[1],
+                    self.handshake[2],
+                    self.handshake[3],
+                ]) as usize;
+                if self.handshake.len() < 4 + len {
+                    break;
+                }
+                messages.push(self.handshake.drain(..4 + len).collect());
+            }
+        }
+        self.buffer.drain(..consumed);
+        messages
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        self.pending.clear();
+        self.buffer = Vec::new();
+        self.handshake = Vec::new();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{decode_hex, CHROME_HELLO_RECORD};
+
+    const CLIENT: ([u8; 4], u16) = ([10, 0, 0, 1], 50000);
+    const SERVER: ([u8; 4], u16) = ([10, 0, 0, 2], 443);
+
+    fn frame(
+        src: ([u8; 4], u16),
+        dst: ([u8; 4], u16),
+        seq: u32,
+        syn: bool,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&0x0800u16.to_be_bytes());
+        // IPv4
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(40 + payload.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&src.0);
+        frame.extend_from_slice(&dst.0);
+        // TCP
+        frame.extend_from_slice(&src.1.to_be_bytes());
+        frame.extend_from_slice(&dst.1.to_be_bytes());
+        frame.extend_from_slice(&seq.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 0x50, if syn { 0x02 } else { 0x18 }]);
+        frame.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn record(content_type: u8, fragment: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, 3, 3];
+        record.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+        record.extend_from_slice(fragment);
+        record
+    }
+
+    fn server_hello() -> Vec<u8> {
+        let mut body = vec![3, 3];
+        body.extend_from_slice(&[0x11; 32]);
+        body.extend_from_slice(&[0, 0x13, 0x01, 0]);
+        let mut exts = vec![0, 0x2b, 0, 2, 3, 4, 0, 0x33, 0, 0x24, 0, 0x1d, 0, 0x20];
+        exts.extend_from_slice(&[0x22; 32]);
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend_from_slice(&exts);
+        let mut msg = vec![2, 0];
+        msg.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    /// A handshake where the ClientHello spans two records and three
+    /// reordered segments, one of them retransmitted.
+    fn packets() -> Vec<Vec<u8>> {
+        let hello = decode_hex(CHROME_HELLO_RECORD);
+        let message = &hello[5..];
+        let mut client_stream = record(0x16, &message[..100]);
+        client_stream.extend(record(0x16, &message[100..]));
+        let (first, rest) = client_stream.split_at(150);
+        let (second, third) = rest.split_at(200);
+        let mut server_stream = record(0x16, &server_hello());
+        server_stream.extend(record(0x14, &[1]));
+        server_stream.extend(record(0x17, &[0x33; 64]));
+        vec![
+            frame(CLIENT, SERVER, 1000, true, &[]),
+            frame(SERVER, CLIENT, 5000, true, &[]),
+            frame(CLIENT, SERVER, 1001 + 350, false, third),
+            frame(CLIENT, SERVER, 1001, false, first),
+            frame(CLIENT, SERVER, 1001, false, first),
+            frame(CLIENT, SERVER, 1001 + 150, false, second),
+            frame(SERVER, CLIENT, 5001, false, &server_stream),
+        ]
+    }
+
+    fn check(reports: &[FlowReport]) {
+        let hello = decode_hex(CHROME_HELLO_RECORD);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.client, SocketAddr::from(CLIENT));
+        assert_eq!(report.server, SocketAddr::from(SERVER));
+        assert_eq!(
+            report.client_hellos,
+            vec![ClientHelloInfo::from_bytes(&hello).unwrap()]
+        );
+        assert_eq!(report.server_hellos.len(), 1);
+        assert_eq!(report.server_hellos[0].cipher_suite, 0x1301);
+        assert_eq!(report.server_hellos[0].extensions, vec![0x2b, 0x33]);
+        #[cfg(feature = "ja3")]
+        assert_eq!(
+            report.client_hellos[0].ja3_hash(),
+            "cd08e31494f9531f560d64c695473da9"
+        );
+    }
+
+    #[test]
+    fn test_pcap() {
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&PCAP_MAGIC_US.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0]);
+        capture.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        for packet in packets() {
+            capture.extend_from_slice(&[0; 8]);
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            capture.extend_from_slice(&packet);
+        }
+        check(&read_capture(&capture).unwrap());
+    }
+
+    #[test]
+    fn test_reused_port() {
+        let hello = decode_hex(CHROME_HELLO_RECORD);
+        let mut server_stream = record(0x16, &server_hello());
+        server_stream.extend(record(0x17, &[0x33; 64]));
+        let mut packets = packets();
+        packets.extend([
+            frame(CLIENT, SERVER, 9000, true, &[]),
+            frame(SERVER, CLIENT, 7000, true, &[]),
+            frame(CLIENT, SERVER, 9001, false, &hello),
+            frame(SERVER, CLIENT, 7001, false, &server_stream),
+        ]);
+        let mut extractor = Extractor::default();
+        for packet in packets {
+            extractor.packet(LINKTYPE_ETHERNET, &packet);
+        }
+        let reports = extractor.reports;
+        check(&reports[..1]);
+        check(&reports[1..]);
+    }
+
+    #[test]
+    fn test_pcapng() {
+        fn block(capture: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+            let padded = body.len().div_ceil(4) * 4;
+            let total_len = (12 + padded) as u32;
+            capture.extend_from_slice(&block_type.to_be_bytes());
+            capture.extend_from_slice(&total_len.to_be_bytes());
+            capture.extend_from_slice(body);
+            capture.resize(capture.len() + padded - body.len(), 0);
+            capture.extend_from_slice(&total_len.to_be_bytes());
+        }
+        let mut capture = Vec::new();
+        let mut shb = PCAPNG_BYTE_ORDER_MAGIC.to_be_bytes().to_vec();
+        shb.extend_from_slice(&[0, 1, 0, 0]);
+        shb.extend_from_slice(&u64::MAX.to_be_bytes());
+        block(&mut capture, PCAPNG_SHB, &shb);
+        let mut idb = (LINKTYPE_ETHERNET as u16).to_be_bytes().to_vec();
+        idb.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        block(&mut capture, PCAPNG_IDB, &idb);
+        for packet in packets() {
+            let mut epb = vec![0u8; 12];
+            epb.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+            epb.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+            epb.extend_from_slice(&packet);
+            block(&mut capture, PCAPNG_EPB, &epb);
+        }
+        check(&read_capture(&capture).unwrap());
+    }
+}
@@ -0,0 +1,350 @@
+//! Fixtures shared by the unit tests.
+
+use crate::builder::X25519_MLKEM768;
+use crate::parse::RawClientHello;
+use crate::JAOverride;
+use rustls::crypto::CryptoProvider;
+use rustls::internal::msgs::enums::{HpkeAead, HpkeKdf, HpkeKem};
+use rustls::{ClientConfig, NamedGroup, SignatureScheme};
+use std::sync::Arc;
+
+pub(crate) const CHROME_HELLO_RECORD: &str = concat!(
+    "1603010200010001fc0303000102030405060708090a0b0c0d0e0f1011121314",
+    "15161718191a1b1c1d1e1f200000000000000000000000000000000000000000",
+    "00000000000000000000000000205a5a130113021303c02bc02fc02cc030cca9",
+    "cca8c013c014009c009d002f0035010001930a0a000000000010000e00000b65",
+    "78616d706c652e636f6d00170000ff01000100000a000a00082a2a001d001700",
+    "18000b00020100002300000010000b000908687474702f312e31000500050100",
+    "000000000d001200100403080404010503080505010806060100120000003300",
+    "2b00292a2a000100001d0020000102030405060708090a0b0c0d0e0f10111213",
+    "1415161718191a1b1c1d1e1f002d00020101002b0007063a3a03040303001b00",
+    "030200024469000500030268324a4a000100001500cf00000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000"
+);
+
+/// One draft-18 config: X25519, HKDF-SHA256/AES-128-GCM, public name
+/// "public.example".
+pub(crate) const ECH_CONFIG_LIST: &str = concat!(
+    "0041fe0d003d",
+    "2a00200020",
+    "1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f90a",
+    "000400010001",
+    "00",
+    "0e7075626c69632e6578616d706c65",
+    "0000",
+);
+
+pub(crate) fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// X25519 under the hybrid codepoint, standing in for a provider with a
+/// post-quantum group.
+#[derive(Debug)]
+struct FakeHybrid(&'static dyn rustls::crypto::SupportedKxGroup);
+
+struct FakeHybridKx(Box<dyn rustls::crypto::ActiveKeyExchange>);
+
+impl rustls::crypto::SupportedKxGroup for FakeHybrid {
+    fn start(&self) -> Result<Box<dyn rustls::crypto::ActiveKeyExchange>, rustls::Error> {
+        Ok(Box::new(FakeHybridKx(self.0.start()?)))
+    }
+    fn name(&self) -> NamedGroup {
+        X25519_MLKEM768
+    }
+}
+
+impl rustls::crypto::ActiveKeyExchange for FakeHybridKx {
+    fn complete(
+        self: Box<Self>,
+        peer_pub_key: &[u8],
+    ) -> Result<rustls::crypto::SharedSecret, rustls::Error> {
+        self.0.complete(peer_pub_key)
+    }
+    fn pub_key(&self) -> &[u8] {
+        self.0.pub_key()
+    }
+    fn group(&self) -> NamedGroup {
+        X25519_MLKEM768
+    }
+}
+
+/// The default provider, with [`FakeHybrid`] after its own groups.
+fn hybrid_provider() -> Arc<CryptoProvider> {
+    let mut provider = (**ClientConfig::builder()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth()
+        .crypto_provider())
+    .clone();
+    let x25519 = provider
+        .kx_groups
+        .iter()
+        .copied()
+        .find(|kx| kx.name() == NamedGroup::X25519)
+        .unwrap();
+    provider
+        .kx_groups
+        .push(Box::leak(Box::new(FakeHybrid(x25519))));
+    Arc::new(provider)
+}
+
+pub(crate) fn hybrid_client_config() -> ClientConfig {
+    ClientConfig::builder_with_provider(hybrid_provider())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth()
+}
+
+/// HPKE for the suite of [`ECH_CONFIG_LIST`] that seals nothing, enough
+/// for a server without ECH, which only reads the outer hello.
+#[derive(Debug)]
+struct FakeHpke;
+
+#[derive(Debug)]
+struct FakeSealer;
+
+impl rustls::crypto::hpke::Hpke for FakeHpke {
+    fn seal(
+        &self,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+        _: &rustls::crypto::hpke::HpkePublicKey,
+    ) -> Result<(rustls::crypto::hpke::EncapsulatedSecret, Vec<u8>), rustls::Error> {
+        unimplemented!()
+    }
+    fn setup_sealer(
+        &self,
+        _: &[u8],
+        _: &rustls::crypto::hpke::HpkePublicKey,
+    ) -> Result<
+        (
+            rustls::crypto::hpke::EncapsulatedSecret,
+            Box<dyn rustls::crypto::hpke::HpkeSealer>,
+        ),
+        rustls::Error,
+    > {
+        Ok((
+            rustls::crypto::hpke::EncapsulatedSecret(vec![0; 32]),
+            Box::new(FakeSealer),
+        ))
+    }
+    fn open(
+        &self,
+        _: &rustls::crypto::hpke::EncapsulatedSecret,
+        _: &[u8],
+        _: &[u8],
+        _: &[u8],
+        _: &rustls::crypto::hpke::HpkePrivateKey,
+    ) -> Result<Vec<u8>, rustls::Error> {
+        unimplemented!()
+    }
+    fn setup_opener(
+        &self,
+        _: &rustls::crypto::hpke::EncapsulatedSecret,
+        _: &[u8],
+        _: &rustls::crypto::hpke::HpkePrivateKey,
+    ) -> Result<Box<dyn rustls::crypto::hpke::HpkeOpener>, rustls::Error> {
+        unimplemented!()
+    }
+    fn generate_key_pair(
+        &self,
+    ) -> Result<
+        (
+            rustls::crypto::hpke::HpkePublicKey,
+            rustls::crypto::hpke::HpkePrivateKey,
+        ),
+        rustls::Error,
+    > {
+        unimplemented!()
+    }
+    fn suite(&self) -> rustls::crypto::hpke::HpkeSuite {
+        rustls::crypto::hpke::HpkeSuite {
+            kem: HpkeKem::DHKEM_X25519_HKDF_SHA256,
+            sym: rustls::internal::msgs::handshake::HpkeSymmetricCipherSuite {
+                kdf_id: HpkeKdf::HKDF_SHA256,
+                aead_id: HpkeAead::AES_128_GCM,
+            },
+        }
+    }
+}
+
+impl rustls::crypto::hpke::HpkeSealer for FakeSealer {
+    fn seal(&mut self, _: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        // same length as the real ciphertext and tag
+        Ok(vec![0; plaintext.len() + 16])
+    }
+}
+
+/// Length of the ClientHello message `hello` was parsed from.
+pub(crate) fn hello_len(hello: &RawClientHello) -> usize {
+    let extensions: usize = hello.extensions.iter().map(|e| 4 + e.payload.len()).sum();
+    4 + 2
+        + 32
+        + (1 + hello.session_id.len())
+        + (2 + 2 * hello.cipher_suites.len())
+        + (1 + hello.compression_methods.len())
+        + (2 + extensions)
+}
+
+/// Connects `overrider` to an in-memory server for localhost that accepts
+/// any of `server_groups`, returning the ClientHellos the server received
+/// and the server name it saw. Both sides also support [`FakeHybrid`].
+pub(crate) fn handshake(
+    overrider: JAOverride,
+    server_groups: &[NamedGroup],
+) -> (Vec<RawClientHello>, Option<String>) {
+    let (hellos, server_name, result) = try_handshake(overrider, server_groups);
+    result.unwrap();
+    (hellos, server_name)
+}
+
+/// Like [`handshake`], but returns the client's error. ECH is offered with
+/// [`FakeHpke`] if `overrider` has an ECHConfigList; the server does not
+/// support it.
+pub(crate) fn try_handshake(
+    overrider: JAOverride,
+    server_groups: &[NamedGroup],
+) -> (
+    Vec<RawClientHello>,
+    Option<String>,
+    Result<(), rustls::Error>,
+) {
+    use rustls::client::danger::{
+        DangerousClientConfig, HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+    };
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+    use rustls::{ClientConnection, DigitallySignedStruct, ServerConfig, ServerConnection};
+
+    // self-signed P-256 certificate for localhost
+    let cert = decode_hex(concat!(
+        "308201953082013ba003020102021452d93f3469e3e312d1c6c4a854352875ff886b6d300a06082a8648ce3d04030230",
+        "143112301006035504030c096c6f63616c686f73743020170d3236313031373137343630375a180f3231323630393233",
+        "3137343630375a30143112301006035504030c096c6f63616c686f73743059301306072a8648ce3d020106082a8648ce",
+        "3d030107034200046e96c479514ef71e4a4936582f8f781a19be9fa50d071372e54584f52ab5f3f9ce863bf275901325",
+        "5c1f2da0e54f6ea6180497facc2cc30820f32b5bcf86e78ea3693067301d0603551d0e041604146ed369ac40736d22e7",
+        "a9b7b1c1a3d1c87057d55d301f0603551d230418301680146ed369ac40736d22e7a9b7b1c1a3d1c87057d55d300f0603",
+        "551d130101ff040530030101ff30140603551d11040d300b82096c6f63616c686f7374300a06082a8648ce3d04030203",
+        "48003045022100f6e4a8fbe1f8179d33772129768e30fa9b2b25544506c0e70b7ef8806d8e54a4022041c7f5a1587989",
+        "a7fca2447c4a1b38f94541841e94f2e344fb340b954b25f8e6",
+    ));
+    let key = decode_hex(concat!(
+        "308187020100301306072a8648ce3d020106082a8648ce3d030107046d306b02010104201afea11f4c48887fd45fb473",
+        "37acbdc5cd5f44cfdfd90b93dbf88f6653bdbe2ca144034200046e96c479514ef71e4a4936582f8f781a19be9fa50d07",
+        "1372e54584f52ab5f3f9ce863bf2759013255c1f2da0e54f6ea6180497facc2cc30820f32b5bcf86e78e",
+    ));
+
+    #[derive(Debug)]
+    struct AcceptAny(Arc<CryptoProvider>);
+    impl ServerCertVerifier for AcceptAny {
+        fn verify_server_cert(
+            &self,
+            _: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &ServerName<'_>,
+            _: &[u8],
+            _: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+        fn verify_tls12_signature(
+            &self,
+            _: &[u8],
+            _: &CertificateDer<'_>,
+            _: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+        fn verify_tls13_signature(
+            &self,
+            _: &[u8],
+            _: &CertificateDer<'_>,
+            _: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            Ok(HandshakeSignatureValid::assertion())
+        }
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    let provider = hybrid_provider();
+    let client_builder = ClientConfig::builder_with_provider(provider.clone());
+    let client_builder = match overrider.ech_mode(&[&FakeHpke]).unwrap() {
+        Some(mode) => client_builder.with_ech(mode).unwrap(),
+        None => client_builder
+            .with_safe_default_protocol_versions()
+            .unwrap(),
+    };
+    let mut client_config = client_builder
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAny(provider.clone())))
+        .with_no_client_auth();
+    DangerousClientConfig {
+        cfg: &mut client_config,
+    }
+    .set_hello_override(Arc::new(overrider));
+
+    let server_provider = CryptoProvider {
+        kx_groups: provider
+            .kx_groups
+            .iter()
+            .copied()
+            .filter(|kx| server_groups.contains(&kx.name()))
+            .collect(),
+        ..(*provider).clone()
+    };
+    let server_config = ServerConfig::builder_with_provider(Arc::new(server_provider))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(cert)],
+            PrivateKeyDer::try_from(key).unwrap(),
+        )
+        .unwrap();
+
+    let mut client = ClientConnection::new(
+        Arc::new(client_config),
+        ServerName::try_from("localhost").unwrap(),
+    )
+    .unwrap();
+    let mut server = ServerConnection::new(Arc::new(server_config)).unwrap();
+    let mut hellos = Vec::new();
+    let result = loop {
+        if !client.is_handshaking() && !server.is_handshaking() {
+            break Ok(());
+        }
+        let mut buf = Vec::new();
+        client.write_tls(&mut buf).unwrap();
+        // a second hello follows a middlebox compatibility ChangeCipherSpec,
+        // the client's Finished is encrypted
+        let mut record = &buf[..];
+        while record.first() == Some(&0x14) {
+            record = &record[5 + usize::from(u16::from_be_bytes([record[3], record[4]]))..];
+        }
+        if record.first() == Some(&0x16) {
+            hellos.push(RawClientHello::parse(record).unwrap());
+        }
+        server.read_tls(&mut &buf[..]).unwrap();
+        server.process_new_packets().unwrap();
+        let mut buf = Vec::new();
+        server.write_tls(&mut buf).unwrap();
+        client.read_tls(&mut &buf[..]).unwrap();
+        if let Err(err) = client.process_new_packets() {
+            break Err(err);
+        }
+    };
+
+    (hellos, server.server_name().map(str::to_string), result)
+}